@@ -0,0 +1,276 @@
+use crate::disassembler::{function::Function, instruction::Mnemonic, operand::Operand};
+
+/// Calls from one function to another, merged into a single edge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallEdge {
+    caller: String,
+    callee: String,
+    count: usize,
+}
+
+impl CallEdge {
+    /// Returns the ID of the calling function.
+    pub fn caller(&self) -> &str {
+        &self.caller
+    }
+
+    /// Returns the ID of the called function.
+    pub fn callee(&self) -> &str {
+        &self.callee
+    }
+
+    /// Returns the number of call sites in the caller which call the callee.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+#[derive(Debug)]
+pub struct CallGraph {
+    functions: Vec<String>,
+    edges: Vec<CallEdge>,
+}
+
+impl CallGraph {
+    /// Builds the call graph of the given functions. Functions are kept in
+    /// definition order, and the edges of each caller in the order of their
+    /// first call site.
+    pub fn build(functions: &[Function]) -> CallGraph {
+        let mut edges: Vec<CallEdge> = Vec::new();
+        for function in functions {
+            let first_edge = edges.len();
+            for instr in function.all_instructions() {
+                let (Mnemonic::CALL, Some(Operand::FunctionReference(func_ref))) =
+                    (instr.mnemonic(), instr.operands().first())
+                else {
+                    continue;
+                };
+                match edges[first_edge..]
+                    .iter_mut()
+                    .find(|edge| edge.callee == func_ref.id())
+                {
+                    Some(edge) => edge.count += 1,
+                    None => edges.push(CallEdge {
+                        caller: function.id().to_string(),
+                        callee: func_ref.id().to_string(),
+                        count: 1,
+                    }),
+                }
+            }
+        }
+
+        CallGraph {
+            functions: functions.iter().map(|func| func.id().to_string()).collect(),
+            edges,
+        }
+    }
+
+    /// Returns the IDs of the functions, in definition order.
+    pub fn functions(&self) -> &Vec<String> {
+        &self.functions
+    }
+
+    /// Returns the edges of the call graph.
+    pub fn edges(&self) -> &Vec<CallEdge> {
+        &self.edges
+    }
+
+    /// Returns the edges of the calls made by a function.
+    pub fn callees(&self, id: &str) -> Vec<&CallEdge> {
+        self.edges.iter().filter(|edge| edge.caller == id).collect()
+    }
+
+    /// Returns the edges of the calls made to a function.
+    pub fn callers(&self, id: &str) -> Vec<&CallEdge> {
+        self.edges.iter().filter(|edge| edge.callee == id).collect()
+    }
+
+    /// Returns the functions which are never called, such as the algorithm
+    /// snippet.
+    pub fn roots(&self) -> Vec<&str> {
+        self.functions
+            .iter()
+            .filter(|id| self.callers(id).is_empty())
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Returns the functions which call no other function, such as `n00`.
+    pub fn leaves(&self) -> Vec<&str> {
+        self.functions
+            .iter()
+            .filter(|id| self.callees(id).is_empty())
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Exports the call graph in the DOT format of Graphviz. Roots are drawn
+    /// as boxes, and edges with several call sites are labelled with their
+    /// count.
+    pub fn to_dot(&self) -> String {
+        let roots = self.roots();
+        let mut lines = vec![String::from("digraph calls {")];
+        for id in &self.functions {
+            let shape = if roots.contains(&id.as_str()) {
+                ", shape=box"
+            } else {
+                ""
+            };
+            lines.push(format!(
+                "    {} [label={}{shape}];",
+                quote(id),
+                quote(&name(id))
+            ));
+        }
+        for edge in &self.edges {
+            let label = if edge.count > 1 {
+                format!(" [label={}]", edge.count)
+            } else {
+                String::new()
+            };
+            lines.push(format!(
+                "    {} -> {}{label};",
+                quote(&edge.caller),
+                quote(&edge.callee)
+            ));
+        }
+        lines.push(String::from("}"));
+        lines.join("\n") + "\n"
+    }
+
+    /// Exports the call graph as JSON, listing the functions, the edges with
+    /// their counts, the roots and the leaves.
+    pub fn to_json(&self) -> String {
+        let functions: Vec<String> = self
+            .functions
+            .iter()
+            .map(|id| {
+                format!(
+                    "    {{\"id\": {}, \"name\": {}}}",
+                    quote(id),
+                    quote(&name(id))
+                )
+            })
+            .collect();
+        let edges: Vec<String> = self
+            .edges
+            .iter()
+            .map(|edge| {
+                format!(
+                    "    {{\"caller\": {}, \"callee\": {}, \"count\": {}}}",
+                    quote(&edge.caller),
+                    quote(&edge.callee),
+                    edge.count
+                )
+            })
+            .collect();
+        let list = |ids: Vec<&str>| {
+            let ids: Vec<String> = ids.into_iter().map(quote).collect();
+            format!("[{}]", ids.join(", "))
+        };
+
+        format!(
+            "{{\n  \"functions\": [\n{}\n  ],\n  \"edges\": [\n{}\n  ],\n  \"roots\": {},\n  \"leaves\": {}\n}}\n",
+            functions.join(",\n"),
+            edges.join(",\n"),
+            list(self.roots()),
+            list(self.leaves())
+        )
+    }
+}
+
+/// Returns the name a function is displayed with.
+fn name(id: &str) -> String {
+    format!("func_{id}")
+}
+
+/// Quotes a string for DOT or JSON, escaping quotes, backslashes and control
+/// characters.
+fn quote(string: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{quote, CallGraph};
+    use crate::disassembler::disassembler::Disassembler;
+
+    /// Builds the call graph of some bytecode.
+    fn build(bytecode: &str) -> CallGraph {
+        let mut disassembler = Disassembler::new(bytecode.to_string());
+        disassembler.disassemble().unwrap();
+        CallGraph::build(disassembler.functions())
+    }
+
+    #[test]
+    fn call_sites_are_merged_into_edges() {
+        // calls within stores count as call sites too
+        let graph = build(":f:2;:g:3;:t:^f^g^fa=^f;;");
+        let edges: Vec<(&str, &str, usize)> = graph
+            .edges()
+            .iter()
+            .map(|edge| (edge.caller(), edge.callee(), edge.count()))
+            .collect();
+        assert_eq!(edges, [("t", "f", 3), ("t", "g", 1)]);
+        assert_eq!(graph.callers("f").len(), 1);
+        assert_eq!(graph.roots(), ["t"]);
+        assert_eq!(graph.leaves(), ["f", "g"]);
+
+        let dot = graph.to_dot();
+        assert!(dot.contains("    \"t\" -> \"f\" [label=3];\n"), "{dot}");
+        assert!(dot.contains("    \"t\" -> \"g\";\n"), "{dot}");
+    }
+
+    #[test]
+    fn shipped_roots_and_leaves() {
+        let bytecode = fs::read_to_string("input/bytecode.txt").unwrap();
+        let algo = fs::read_to_string("input/algo.txt").unwrap();
+        let mut disassembler = Disassembler::new(bytecode);
+        disassembler.disassemble().unwrap();
+        disassembler
+            .disassemble_snippet(String::from("algorithm"), algo)
+            .unwrap();
+        let graph = CallGraph::build(disassembler.functions());
+
+        // `nb` is defined but never called
+        assert_eq!(graph.roots(), ["nb", "algorithm"]);
+        assert!(graph.leaves().contains(&"n00"));
+        assert!(!graph.leaves().contains(&"g1"));
+        assert!(graph
+            .to_dot()
+            .contains("\"algorithm\" [label=\"func_algorithm\", shape=box];"));
+    }
+
+    #[test]
+    fn names_are_escaped() {
+        assert_eq!(quote("a\"b\\c\nd\u{1}"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+
+        let graph = build(":a\"b:2;:t:^a\"b;");
+        let dot = graph.to_dot();
+        assert!(
+            dot.contains("    \"a\\\"b\" [label=\"func_a\\\"b\"];"),
+            "{dot}"
+        );
+        assert!(dot.contains("    \"t\" -> \"a\\\"b\";"), "{dot}");
+        let json = graph.to_json();
+        assert!(
+            json.contains("{\"id\": \"a\\\"b\", \"name\": \"func_a\\\"b\"}"),
+            "{json}"
+        );
+        assert!(json.contains("\"roots\": [\"t\"]"), "{json}");
+        assert!(json.contains("\"leaves\": [\"a\\\"b\"]"), "{json}");
+    }
+}
@@ -0,0 +1,918 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+    hint::black_box,
+};
+
+use crate::{
+    disassembler::{
+        function::Function,
+        instruction::{Instruction, Mnemonic},
+        operand::{Literal, Operand},
+        stack_effect::StackEffect,
+    },
+    emulator::{
+        dialect::VmDialect,
+        emulator::{fmax, fmin, NUM_ARGS},
+        encoding::b2f,
+    },
+};
+
+use super::{
+    gates::{classify, Bit, Lane},
+    truth_table::Input,
+};
+
+/// Maximum number of nested calls while lifting.
+const MAX_CALL_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Wire(usize);
+
+impl Wire {
+    /// Returns the index of the node driving the wire.
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+impl Display for Wire {
+    /// Writes the name of the wire.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "w{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Node {
+    Constant(bool),
+    Input(Bit),
+    /// A lookup table over up to six wires. Bit `j` of the table is the value
+    /// of the node when input `i` has the value of bit `i` of `j`.
+    Lut(Vec<Wire>, u64),
+}
+
+/// A value on the VM's stack. Values are 0.0, -0.0, Infinity, -Infinity or
+/// NaN, so need a bit on top of the two lanes of the 2-bit encoding.
+#[derive(Debug, Clone, Copy)]
+struct Value {
+    nan: Wire,
+    infinite: Wire,
+    sign: Wire,
+}
+
+impl Value {
+    /// Returns the wires of the value, in the order they index a table.
+    fn wires(&self) -> [Wire; 3] {
+        [self.nan, self.infinite, self.sign]
+    }
+}
+
+/// Decodes the bits of a value. The infinite bit is ignored for NaNs.
+fn decode(bits: usize) -> f32 {
+    let (nan, infinite, sign) = (bits & 1 != 0, bits & 2 != 0, bits & 4 != 0);
+    if nan {
+        f32::from_bits(0x7fc00000 | (sign as u32) << 31)
+    } else {
+        b2f(infinite as u8 | (sign as u8) << 1)
+    }
+}
+
+/// Encodes a value as its NaN, infinite and sign bits. NaNs are not finite,
+/// so the infinite and sign bits are those `f2b` reads.
+fn encode(value: f32) -> [bool; 3] {
+    [value.is_nan(), !value.is_finite(), value.is_sign_negative()]
+}
+
+/// Returns the tables for each bit of the result of an operation on one or
+/// two values, computed with the host's floats.
+fn operation_tables(num_operands: usize, operation: impl Fn(f32, f32) -> f32) -> [u64; 3] {
+    let mut tables = [0; 3];
+    for index in 0..1usize << (3 * num_operands) {
+        let left = black_box(decode(index & 7));
+        let right = black_box(decode(index >> 3));
+        for (table, bit) in tables.iter_mut().zip(encode(operation(left, right))) {
+            *table |= (bit as u64) << index;
+        }
+    }
+    tables
+}
+
+/// Rewrites a table over `num_vars` variables, where `mapping` gives each
+/// variable either a constant or the variable it becomes.
+fn remap(table: u64, num_vars: usize, mapping: &[Result<usize, bool>], new_vars: usize) -> u64 {
+    let mut remapped = 0;
+    for j in 0..1usize << new_vars {
+        let index = (0..num_vars).fold(0, |index, var| {
+            let bit = match mapping[var] {
+                Ok(new_var) => (j >> new_var) & 1,
+                Err(value) => value as usize,
+            };
+            index | bit << var
+        });
+        remapped |= ((table >> index) & 1) << j;
+    }
+    remapped
+}
+
+/// Evaluates a table over bit-sliced operands, by repeatedly selecting
+/// between the halves of the table with the first remaining operand. The
+/// words are scratch space for each entry of the table.
+fn evaluate_table<const N: usize>(
+    table: u64,
+    operands: &[[u64; N]],
+    words: &mut [[u64; N]; 64],
+) -> [u64; N] {
+    for (j, word) in words.iter_mut().enumerate().take(1 << operands.len()) {
+        *word = [0u64.wrapping_sub((table >> j) & 1); N];
+    }
+    for (var, operand) in operands.iter().enumerate() {
+        for i in 0..1 << (operands.len() - var - 1) {
+            let (low, high) = (words[2 * i], words[2 * i + 1]);
+            for (k, word) in words[i].iter_mut().enumerate() {
+                *word = (operand[k] & high[k]) | (!operand[k] & low[k]);
+            }
+        }
+    }
+    words[0]
+}
+
+#[derive(Debug)]
+pub struct Circuit {
+    function_id: String,
+    nodes: Vec<Node>,
+    wires: HashMap<Node, Wire>,
+    inputs: Vec<Wire>,
+    outputs: Vec<[Wire; 2]>,
+}
+
+impl Circuit {
+    /// Lifts a function to a boolean circuit, inlining every call. The
+    /// circuit has a wire for each lane of each parameter, of each global
+    /// variable read and of each value left on the stack.
+    pub fn lift(
+        function: &Function,
+        functions: &[Function],
+        stack_effects: &HashMap<String, StackEffect>,
+        dialect: VmDialect,
+    ) -> Result<Circuit, Box<dyn Error>> {
+        let effect = stack_effects
+            .get(function.id())
+            .ok_or(format!("Unknown stack effect for {}", function.id()))?;
+        let slots = effect.consumed().max(NUM_ARGS);
+        Circuit::lift_with_stack(function, functions, slots, Some(effect), dialect)
+    }
+
+    /// Lifts a function in the same way the harness runs top level bytecode,
+    /// with the parameters as arguments and an empty stack. Every value left
+    /// on the stack is an output.
+    pub fn lift_top_level(
+        function: &Function,
+        functions: &[Function],
+        dialect: VmDialect,
+    ) -> Result<Circuit, Box<dyn Error>> {
+        Circuit::lift_with_stack(function, functions, 0, None, dialect)
+    }
+
+    /// Lifts a function with the given number of parameters on the stack.
+    /// The values the function produces are the outputs if its stack effect
+    /// is given, otherwise the whole stack is.
+    fn lift_with_stack(
+        function: &Function,
+        functions: &[Function],
+        slots: usize,
+        effect: Option<&StackEffect>,
+        dialect: VmDialect,
+    ) -> Result<Circuit, Box<dyn Error>> {
+        let mut circuit = Circuit {
+            function_id: function.id().to_string(),
+            nodes: Vec::new(),
+            wires: HashMap::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        };
+        let mut lifter = Lifter::new(&mut circuit, functions, dialect);
+
+        let params: Vec<Value> = (0..slots.max(NUM_ARGS))
+            .map(|i| lifter.input_value(Input::Parameter(i)))
+            .collect();
+        lifter.stack.extend(params[..slots].iter().rev());
+        lifter.execute_function(function, params[..NUM_ARGS].to_vec(), 0)?;
+
+        let stack = lifter.stack;
+        let num_outputs = match effect {
+            Some(effect) => {
+                if stack.len() != slots - effect.consumed() + effect.produced() {
+                    return Err(format!(
+                        "Unexpected stack size after lifting func_{}",
+                        function.id()
+                    )
+                    .into());
+                }
+                effect.produced()
+            }
+            None => stack.len(),
+        };
+        circuit.outputs = stack[stack.len() - num_outputs..]
+            .iter()
+            .map(|value| [value.infinite, value.sign])
+            .collect();
+        circuit.prune();
+        Ok(circuit)
+    }
+
+    /// Returns the ID of the lifted function.
+    pub fn function_id(&self) -> &str {
+        &self.function_id
+    }
+
+    /// Returns the nodes, indexed by the wire they drive. Nodes only read
+    /// wires driven by earlier nodes.
+    pub fn nodes(&self) -> &Vec<Node> {
+        &self.nodes
+    }
+
+    /// Returns the node driving a wire.
+    pub fn node(&self, wire: Wire) -> &Node {
+        &self.nodes[wire.0]
+    }
+
+    /// Returns the input wires.
+    pub fn inputs(&self) -> &Vec<Wire> {
+        &self.inputs
+    }
+
+    /// Returns the wires of the infinite and sign lanes of each output, with
+    /// the top of the stack last.
+    pub fn outputs(&self) -> &Vec<[Wire; 2]> {
+        &self.outputs
+    }
+
+    /// Returns the inputs a wire depends on.
+    pub fn support(&self, wire: Wire) -> Vec<Bit> {
+        let mut visited = HashSet::new();
+        let mut worklist = vec![wire];
+        let mut support = Vec::new();
+        while let Some(wire) = worklist.pop() {
+            if !visited.insert(wire) {
+                continue;
+            }
+            match self.node(wire) {
+                Node::Constant(_) => {}
+                Node::Input(bit) => support.push(*bit),
+                Node::Lut(inputs, _) => worklist.extend(inputs),
+            }
+        }
+        support.sort_by_key(|bit| (bit.input(), bit.lane().map(|lane| lane == Lane::Sign)));
+        support
+    }
+
+    /// Evaluates the circuit, returning the lanes of each output. Inputs
+    /// which are not given are false.
+    pub fn evaluate(&self, inputs: &HashMap<Bit, bool>) -> Vec<[bool; 2]> {
+        let inputs = inputs
+            .iter()
+            .map(|(bit, value)| (*bit, [0u64.wrapping_sub(*value as u64)]))
+            .collect();
+        self.evaluate_sliced(&inputs)
+            .iter()
+            .map(|lanes| lanes.map(|[value]| value & 1 == 1))
+            .collect()
+    }
+
+    /// Evaluates the circuit `64 * N` times at once, where bit `k` of word
+    /// `i` of each value belongs to evaluation `64 * i + k`. Inputs which are
+    /// not given are false.
+    pub fn evaluate_sliced<const N: usize>(
+        &self,
+        inputs: &HashMap<Bit, [u64; N]>,
+    ) -> Vec<[[u64; N]; 2]> {
+        let mut values: Vec<[u64; N]> = Vec::with_capacity(self.nodes.len());
+        let mut words = Box::new([[0; N]; 64]);
+        for node in &self.nodes {
+            let value = match node {
+                Node::Constant(value) => [0u64.wrapping_sub(*value as u64); N],
+                Node::Input(bit) => inputs.get(bit).copied().unwrap_or([0; N]),
+                Node::Lut(wires, table) => {
+                    let mut operands = [[0; N]; 6];
+                    for (operand, wire) in operands.iter_mut().zip(wires) {
+                        *operand = values[wire.0];
+                    }
+                    evaluate_table(*table, &operands[..wires.len()], &mut words)
+                }
+            };
+            values.push(value);
+        }
+        self.outputs
+            .iter()
+            .map(|lanes| lanes.map(|wire| values[wire.0]))
+            .collect()
+    }
+
+    /// Returns a copy of the circuit with some inputs fixed, simplifying the
+    /// nodes which depend on them.
+    pub fn specialise(&self, fixed: &HashMap<Bit, bool>) -> Circuit {
+        let mut circuit = Circuit {
+            function_id: self.function_id.clone(),
+            nodes: Vec::new(),
+            wires: HashMap::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        };
+
+        let mut renumbered = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let wire = match node {
+                Node::Input(bit) => match fixed.get(bit) {
+                    Some(value) => circuit.add_node(Node::Constant(*value)),
+                    None => {
+                        let wire = circuit.add_node(node.clone());
+                        circuit.inputs.push(wire);
+                        wire
+                    }
+                },
+                Node::Constant(_) => circuit.add_node(node.clone()),
+                Node::Lut(inputs, table) => {
+                    let inputs: Vec<Wire> = inputs.iter().map(|wire| renumbered[wire.0]).collect();
+                    circuit.add_lut(&inputs, *table)
+                }
+            };
+            renumbered.push(wire);
+        }
+
+        circuit.outputs = self
+            .outputs
+            .iter()
+            .map(|lanes| lanes.map(|wire| renumbered[wire.0]))
+            .collect();
+        circuit.prune();
+        circuit
+    }
+
+    /// Returns the circuit in the Berkeley Logic Interchange Format.
+    pub fn to_blif(&self) -> String {
+        let mut lines = vec![format!(".model func_{}", self.function_id)];
+        let inputs: Vec<String> = self
+            .inputs
+            .iter()
+            .map(|wire| self.wire_name(*wire))
+            .collect();
+        lines.push(format!(".inputs {}", inputs.join(" ")));
+        lines.push(format!(".outputs {}", self.output_names().join(" ")));
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            let wire = Wire(index);
+            match node {
+                Node::Constant(value) => {
+                    lines.push(format!(".names {wire}"));
+                    if *value {
+                        lines.push(String::from("1"));
+                    }
+                }
+                Node::Input(_) => {
+                    lines.push(format!(".names {} {wire}", self.wire_name(wire)));
+                    lines.push(String::from("1 1"));
+                }
+                Node::Lut(inputs, table) => {
+                    let names: Vec<String> = inputs.iter().map(|wire| wire.to_string()).collect();
+                    lines.push(format!(".names {} {wire}", names.join(" ")));
+                    for j in (0..1usize << inputs.len()).filter(|j| (table >> j) & 1 == 1) {
+                        let row: String = (0..inputs.len())
+                            .map(|i| if (j >> i) & 1 == 1 { '1' } else { '0' })
+                            .collect();
+                        lines.push(format!("{row} 1"));
+                    }
+                }
+            }
+        }
+
+        let wires = self.outputs.iter().flatten();
+        for (name, wire) in self.output_names().iter().zip(wires) {
+            lines.push(format!(".names {wire} {name}"));
+            lines.push(String::from("1 1"));
+        }
+        lines.push(String::from(".end"));
+        lines.join("\n")
+    }
+
+    /// Returns the name of an input wire.
+    fn wire_name(&self, wire: Wire) -> String {
+        match self.node(wire) {
+            Node::Input(bit) => bit.to_string(),
+            _ => wire.to_string(),
+        }
+    }
+
+    /// Returns the names of the output lanes.
+    fn output_names(&self) -> Vec<String> {
+        (0..self.outputs.len())
+            .flat_map(|i| Lane::all().map(|lane| format!("out_{i}.{lane}")))
+            .collect()
+    }
+
+    /// Returns the wire driven by a node, adding the node if there is not
+    /// one already.
+    fn add_node(&mut self, node: Node) -> Wire {
+        if let Some(wire) = self.wires.get(&node) {
+            return *wire;
+        }
+        let wire = Wire(self.nodes.len());
+        self.nodes.push(node.clone());
+        self.wires.insert(node, wire);
+        wire
+    }
+
+    /// Returns a wire computing a table over the given wires. Constant,
+    /// repeated and unused inputs are removed first.
+    fn add_lut(&mut self, inputs: &[Wire], table: u64) -> Wire {
+        let (inputs, table) = self.collapse(inputs.to_vec(), table);
+
+        let mut wires: Vec<Wire> = Vec::new();
+        let mapping: Vec<Result<usize, bool>> = inputs
+            .iter()
+            .map(|wire| match self.node(*wire) {
+                Node::Constant(value) => Err(*value),
+                _ => Ok(wires.iter().position(|w| w == wire).unwrap_or_else(|| {
+                    wires.push(*wire);
+                    wires.len() - 1
+                })),
+            })
+            .collect();
+        let table = remap(table, inputs.len(), &mapping, wires.len());
+
+        // keep the inputs the table depends on, ordered by wire
+        let mut support: Vec<usize> = (0..wires.len())
+            .filter(|var| {
+                (0..1usize << wires.len())
+                    .any(|j| (table >> j) & 1 != (table >> (j ^ (1 << var))) & 1)
+            })
+            .collect();
+        support.sort_by_key(|var| wires[*var]);
+        let mapping: Vec<Result<usize, bool>> = (0..wires.len())
+            .map(|var| support.iter().position(|v| *v == var).ok_or(false))
+            .collect();
+        let table = remap(table, wires.len(), &mapping, support.len());
+
+        match support.len() {
+            0 => self.add_node(Node::Constant(table & 1 == 1)),
+            1 if table == 0b10 => wires[support[0]],
+            _ => {
+                let inputs = support.iter().map(|var| wires[*var]).collect();
+                self.add_node(Node::Lut(inputs, table))
+            }
+        }
+    }
+
+    /// Merges tables feeding into a table into it, as long as that does not
+    /// give it more inputs. This folds patterns such as `and(x, not(x))`.
+    fn collapse(&self, mut inputs: Vec<Wire>, mut table: u64) -> (Vec<Wire>, u64) {
+        let mut var = 0;
+        while var < inputs.len() {
+            let (sub_inputs, sub_table) = match self.node(inputs[var]) {
+                Node::Lut(sub_inputs, sub_table) => (sub_inputs, *sub_table),
+                _ => {
+                    var += 1;
+                    continue;
+                }
+            };
+
+            let mut merged: Vec<Wire> = inputs[..var].to_vec();
+            merged.extend(&inputs[var + 1..]);
+            for wire in sub_inputs {
+                if !merged.contains(wire) {
+                    merged.push(*wire);
+                }
+            }
+            if merged.len() > inputs.len() {
+                var += 1;
+                continue;
+            }
+
+            let position = |wire: &Wire| merged.iter().position(|w| w == wire).unwrap_or(0);
+            let mut merged_table = 0;
+            for j in 0..1usize << merged.len() {
+                let sub_index = sub_inputs.iter().enumerate().fold(0, |index, (i, wire)| {
+                    index | ((j >> position(wire)) & 1) << i
+                });
+                let sub_value = ((sub_table >> sub_index) & 1) as usize;
+                let index = inputs.iter().enumerate().fold(0, |index, (i, wire)| {
+                    let bit = if i == var {
+                        sub_value
+                    } else {
+                        (j >> position(wire)) & 1
+                    };
+                    index | bit << i
+                });
+                merged_table |= ((table >> index) & 1) << j;
+            }
+
+            // the merged inputs are all driven by earlier nodes, so this ends
+            inputs = merged;
+            table = merged_table;
+            var = 0;
+        }
+        (inputs, table)
+    }
+
+    /// Removes the nodes which no output depends on, apart from inputs.
+    fn prune(&mut self) {
+        let mut live = vec![false; self.nodes.len()];
+        for wire in self.inputs.iter().chain(self.outputs.iter().flatten()) {
+            live[wire.0] = true;
+        }
+        for index in (0..self.nodes.len()).rev() {
+            if let (true, Node::Lut(inputs, _)) = (live[index], &self.nodes[index]) {
+                for wire in inputs {
+                    live[wire.0] = true;
+                }
+            }
+        }
+
+        let mut renumbered = vec![Wire(0); self.nodes.len()];
+        let nodes = std::mem::take(&mut self.nodes);
+        self.wires.clear();
+        for (index, node) in nodes.into_iter().enumerate() {
+            if !live[index] {
+                continue;
+            }
+            let node = match node {
+                Node::Lut(inputs, table) => Node::Lut(
+                    inputs.iter().map(|wire| renumbered[wire.0]).collect(),
+                    table,
+                ),
+                node => node,
+            };
+            renumbered[index] = self.add_node(node);
+        }
+        for wire in self
+            .inputs
+            .iter_mut()
+            .chain(self.outputs.iter_mut().flatten())
+        {
+            *wire = renumbered[wire.0];
+        }
+    }
+}
+
+impl Display for Circuit {
+    /// Writes the circuit as a netlist, naming each table after the gate it
+    /// implements where it is a known primitive.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "circuit func_{}", self.function_id)?;
+        for (index, node) in self.nodes.iter().enumerate() {
+            let wire = Wire(index);
+            match node {
+                Node::Constant(value) => writeln!(f, "{wire} = {}", *value as u8)?,
+                Node::Input(bit) => writeln!(f, "{wire} = input {bit}")?,
+                Node::Lut(inputs, table) => {
+                    let function: Vec<bool> = (0..1 << inputs.len())
+                        .map(|j| (table >> j) & 1 == 1)
+                        .collect();
+                    match classify(&function, inputs.len()) {
+                        Some((kind, vars)) => {
+                            let names: Vec<String> =
+                                vars.iter().map(|var| inputs[*var].to_string()).collect();
+                            writeln!(f, "{wire} = {kind}({})", names.join(", "))?
+                        }
+                        None => {
+                            let names: Vec<String> =
+                                inputs.iter().map(|wire| wire.to_string()).collect();
+                            writeln!(f, "{wire} = lut_{table:#x}({})", names.join(", "))?
+                        }
+                    }
+                }
+            }
+        }
+
+        let wires = self.outputs.iter().flatten();
+        for (name, wire) in self.output_names().iter().zip(wires) {
+            writeln!(f, "output {name} = {wire}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Symbolically runs functions over values made of wires.
+struct Lifter<'a, 'b> {
+    circuit: &'b mut Circuit,
+    functions: HashMap<&'a str, &'a Function>,
+    stack: Vec<Value>,
+    variables: HashMap<char, Value>,
+    unary_tables: HashMap<&'static str, [u64; 3]>,
+    binary_tables: HashMap<&'static str, [u64; 3]>,
+    dialect: VmDialect,
+}
+
+impl<'a, 'b> Lifter<'a, 'b> {
+    /// Creates a new lifter which adds nodes to a circuit.
+    fn new(
+        circuit: &'b mut Circuit,
+        functions: &'a [Function],
+        dialect: VmDialect,
+    ) -> Lifter<'a, 'b> {
+        let binary_tables = HashMap::from([
+            ("add", operation_tables(2, |l, r| l + r)),
+            ("sub", operation_tables(2, |l, r| l - r)),
+            ("mul", operation_tables(2, |l, r| l * r)),
+            ("div", operation_tables(2, |l, r| l / r)),
+            ("min", operation_tables(2, fmin)),
+            ("max", operation_tables(2, fmax)),
+        ]);
+        let unary_tables = HashMap::from([
+            ("frac", operation_tables(1, |x, _| 1.0 / x)),
+            ("neg", operation_tables(1, |x, _| -x)),
+        ]);
+        Lifter {
+            circuit,
+            functions: functions.iter().map(|func| (func.id(), func)).collect(),
+            stack: Vec::new(),
+            variables: HashMap::new(),
+            unary_tables,
+            binary_tables,
+            dialect,
+        }
+    }
+
+    /// Returns a value which is an input to the circuit. Inputs are never NaN.
+    fn input_value(&mut self, input: Input) -> Value {
+        let [infinite, sign] = Lane::all().map(|lane| {
+            let wire = self
+                .circuit
+                .add_node(Node::Input(Bit::new(input, Some(lane))));
+            self.circuit.inputs.push(wire);
+            wire
+        });
+        Value {
+            nan: self.circuit.add_node(Node::Constant(false)),
+            infinite,
+            sign,
+        }
+    }
+
+    /// Returns a constant value.
+    fn constant_value(&mut self, value: f32) -> Value {
+        let [nan, infinite, sign] =
+            encode(value).map(|bit| self.circuit.add_node(Node::Constant(bit)));
+        Value {
+            nan,
+            infinite,
+            sign,
+        }
+    }
+
+    /// Applies the tables of an operation to its operands.
+    fn apply(&mut self, tables: [u64; 3], operands: &[Value]) -> Value {
+        let inputs: Vec<Wire> = operands.iter().flat_map(|value| value.wires()).collect();
+        let [nan, infinite, sign] = tables.map(|table| self.circuit.add_lut(&inputs, table));
+        Value {
+            nan,
+            infinite,
+            sign,
+        }
+    }
+
+    /// Pops a value from the stack.
+    fn pop(&mut self) -> Result<Value, Box<dyn Error>> {
+        self.stack
+            .pop()
+            .ok_or("Stack underflow, the VM would read garbage here".into())
+    }
+
+    /// Runs the instructions of a function until it returns.
+    fn execute_function(
+        &mut self,
+        function: &'a Function,
+        args: Vec<Value>,
+        depth: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        if depth >= MAX_CALL_DEPTH {
+            return Err(format!("Exceeded max call depth of {MAX_CALL_DEPTH}").into());
+        }
+        for instruction in function.instructions() {
+            if self.execute(instruction, &args, depth)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Lifts an instruction. Returns whether the current function has
+    /// returned.
+    fn execute(
+        &mut self,
+        instruction: &'a Instruction,
+        args: &[Value],
+        depth: usize,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mnemonic = instruction.mnemonic();
+        match mnemonic {
+            Mnemonic::PUSH => {
+                let value = match instruction.operands().first() {
+                    Some(Operand::Literal(Literal::ZERO)) => self.constant_value(0.0),
+                    Some(Operand::Literal(Literal::INFINITY)) => self.constant_value(f32::INFINITY),
+                    Some(Operand::GlobalVariable(var)) => {
+                        let name = var.id().chars().next().ok_or("Empty global variable")?;
+                        match self.variables.get(&name) {
+                            Some(value) => *value,
+                            None => {
+                                let value = self.input_value(Input::GlobalVariable(name));
+                                self.variables.insert(name, value);
+                                value
+                            }
+                        }
+                    }
+                    Some(Operand::Parameter(param)) => *args
+                        .get(param.index())
+                        .ok_or(format!("Parameter index {} out of range", param.index()))?,
+                    _ => return Err("Expected value operand for push, bad disassembly".into()),
+                };
+                self.stack.push(value);
+            }
+            Mnemonic::POP => {
+                self.pop()?;
+            }
+            Mnemonic::ADD
+            | Mnemonic::SUB
+            | Mnemonic::MUL
+            | Mnemonic::DIV
+            | Mnemonic::MIN
+            | Mnemonic::MAX => {
+                let first = self.pop()?;
+                let second = self.pop()?;
+                let (left, right) = self.dialect.operands(first, second);
+                let tables = self.binary_tables[mnemonic.to_string().as_str()];
+                let value = self.apply(tables, &[left, right]);
+                self.stack.push(value);
+            }
+            Mnemonic::FRAC | Mnemonic::NEG => {
+                let operand = self.pop()?;
+                let tables = self.unary_tables[mnemonic.to_string().as_str()];
+                let value = self.apply(tables, &[operand]);
+                self.stack.push(value);
+            }
+            Mnemonic::CALL => {
+                let id = match instruction.operands().first() {
+                    Some(Operand::FunctionReference(func_ref)) => func_ref.id(),
+                    _ => return Err("Expected function reference for call, bad disassembly".into()),
+                };
+                let callee = *self
+                    .functions
+                    .get(id)
+                    .ok_or(format!("Unknown function {id}"))?;
+
+                // the top 8 slots are copied into the arguments without being
+                // popped, slots below the bottom of the stack are left as zero
+                let mut args = Vec::with_capacity(NUM_ARGS);
+                for j in 0..NUM_ARGS {
+                    let value = if j < self.stack.len() {
+                        self.stack[self.stack.len() - j - 1]
+                    } else {
+                        self.constant_value(0.0)
+                    };
+                    args.push(value);
+                }
+                self.execute_function(callee, args, depth + 1)?;
+            }
+            Mnemonic::STORE => {
+                let name = match instruction.operands().first() {
+                    Some(Operand::GlobalVariable(var)) => {
+                        var.id().chars().next().ok_or("Empty global variable")?
+                    }
+                    _ => return Err("Expected global variable for store, bad disassembly".into()),
+                };
+                for instruction in instruction.block() {
+                    if self.execute(instruction, args, depth)? {
+                        return Ok(true);
+                    }
+                }
+                let value = self.pop()?;
+                self.variables.insert(name, value);
+            }
+            Mnemonic::UNKNOWN => {}
+            Mnemonic::INVALID => return Err("Cannot lift invalid bytecode".into()),
+            Mnemonic::RET => return Ok(true),
+        }
+
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs};
+
+    use super::{Circuit, Node};
+    use crate::{
+        analysis::{
+            gates::{Bit, Lane},
+            truth_table::TruthTable,
+        },
+        disassembler::disassembler::Disassembler,
+        emulator::{dialect::VmDialect, encoding::f2b},
+    };
+
+    /// Evaluates a BLIF model, returning the value of each output in the
+    /// order they are declared. Every signal must be driven before it is
+    /// read, which holds for the models the circuit writes.
+    fn evaluate_blif(blif: &str, inputs: &HashMap<String, bool>) -> Vec<bool> {
+        let mut signals: HashMap<String, bool> = HashMap::new();
+        let mut outputs = Vec::new();
+        let mut cover: Option<(Vec<bool>, String)> = None;
+
+        for line in blif.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if !line.starts_with('.') {
+                let (operands, output) = cover.as_ref().expect("cover row outside .names");
+                let row = if operands.is_empty() { "" } else { words[0] };
+                assert_eq!(row.len(), operands.len(), "bad cover row {line}");
+                assert_eq!(words.last(), Some(&"1"), "bad cover row {line}");
+                let matches = row.chars().zip(operands.iter()).all(|(c, value)| match c {
+                    '0' => !value,
+                    '1' => *value,
+                    '-' => true,
+                    _ => panic!("bad cover row {line}"),
+                });
+                if matches {
+                    signals.insert(output.clone(), true);
+                }
+                continue;
+            }
+
+            match words[0] {
+                ".model" | ".end" => {}
+                ".inputs" => {
+                    for name in &words[1..] {
+                        signals.insert(name.to_string(), inputs[*name]);
+                    }
+                }
+                ".outputs" => outputs = words[1..].iter().map(|name| name.to_string()).collect(),
+                ".names" => {
+                    let (output, operands) = words[1..].split_last().unwrap();
+                    let operands = operands
+                        .iter()
+                        .map(|name| *signals.get(*name).expect("signal read before driven"))
+                        .collect();
+                    assert!(!signals.contains_key(*output), "{output} driven twice");
+                    signals.insert(output.to_string(), false);
+                    cover = Some((operands, output.to_string()));
+                }
+                _ => panic!("unexpected line {line}"),
+            }
+        }
+
+        outputs.iter().map(|name| signals[name]).collect()
+    }
+
+    #[test]
+    fn gates_match_emulator() {
+        let bytecode = fs::read_to_string("input/bytecode.txt").unwrap();
+        let mut disassembler = Disassembler::new(bytecode);
+        disassembler.disassemble().unwrap();
+        let stack_effects = disassembler.infer_stack_effects().unwrap();
+
+        for id in ["g1", "g2", "g3", "g4"] {
+            let function = disassembler
+                .functions()
+                .iter()
+                .find(|func| func.id() == id)
+                .unwrap();
+            let dialect = VmDialect::default();
+            let table =
+                TruthTable::build(function, disassembler.functions(), &stack_effects, dialect)
+                    .unwrap();
+            let circuit =
+                Circuit::lift(function, disassembler.functions(), &stack_effects, dialect).unwrap();
+            let blif = circuit.to_blif();
+
+            for row in table.rows() {
+                let mut bits = HashMap::new();
+                for (input, value) in table.inputs().iter().zip(row.inputs()) {
+                    for lane in Lane::all() {
+                        bits.insert(Bit::new(*input, Some(lane)), lane.bit(f2b(*value)));
+                    }
+                }
+                let expected: Vec<[bool; 2]> = row
+                    .outputs()
+                    .iter()
+                    .map(|value| Lane::all().map(|lane| lane.bit(f2b(*value))))
+                    .collect();
+                assert_eq!(circuit.evaluate(&bits), expected, "func_{id} on {row:?}");
+
+                // inputs the table does not vary are zero, as in the table
+                let names = circuit
+                    .inputs()
+                    .iter()
+                    .map(|wire| match circuit.node(*wire) {
+                        Node::Input(bit) => {
+                            (bit.to_string(), bits.get(bit).copied().unwrap_or(false))
+                        }
+                        node => panic!("input wire driven by {node:?}"),
+                    })
+                    .collect();
+                let expected: Vec<bool> = expected.into_iter().flatten().collect();
+                assert_eq!(evaluate_blif(&blif, &names), expected, "BLIF of func_{id}");
+            }
+        }
+    }
+}
@@ -0,0 +1,448 @@
+use std::fmt::Display;
+
+use crate::emulator::encoding::f2b;
+
+use super::truth_table::{Input, TruthTable};
+
+/// Maximum number of inputs of a function whose gate is recognised. Every
+/// known primitive reads at most three bits, and the truth table of a
+/// function has 4^n rows, so wider functions are not enumerated.
+pub const MAX_GATE_INPUTS: usize = 3;
+
+/// The bits of the 2-bit encoding, as set by `f2b`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lane {
+    /// Bit 0, set if the value is not finite.
+    Infinite,
+    /// Bit 1, set if the value is negative.
+    Sign,
+}
+
+impl Lane {
+    /// Returns both lanes, ordered by their bit.
+    pub fn all() -> [Lane; 2] {
+        [Lane::Infinite, Lane::Sign]
+    }
+
+    /// Returns the bit of a 2-bit code which the lane holds.
+    pub fn bit(&self, code: u8) -> bool {
+        match self {
+            Lane::Infinite => code & 1 != 0,
+            Lane::Sign => code & 2 != 0,
+        }
+    }
+}
+
+impl Display for Lane {
+    /// Writes the name of the lane.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Lane::Infinite => write!(f, "inf"),
+            Lane::Sign => write!(f, "sign"),
+        }
+    }
+}
+
+/// An input to a gate. Bitwise gates read the same lane of each input as the
+/// lane they write, so their bits have no lane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Bit {
+    input: Input,
+    lane: Option<Lane>,
+}
+
+impl Bit {
+    /// Creates a new bit.
+    pub fn new(input: Input, lane: Option<Lane>) -> Bit {
+        Bit { input, lane }
+    }
+
+    /// Returns the input the bit is read from.
+    pub fn input(&self) -> Input {
+        self.input
+    }
+
+    /// Returns the lane the bit is read from, or `None` if it is read from
+    /// the lane being written.
+    pub fn lane(&self) -> Option<Lane> {
+        self.lane
+    }
+}
+
+impl Display for Bit {
+    /// Writes the input and lane of the bit.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.lane {
+            Some(lane) => write!(f, "{}.{lane}", self.input),
+            None => write!(f, "{}", self.input),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GateKind {
+    Constant(bool),
+    Buffer,
+    Not,
+    And,
+    Or,
+    Xor,
+    Nand,
+    Nor,
+    Xnor,
+    /// Selects the second input if the first is set, otherwise the third.
+    Mux,
+    /// The sum bit of a full adder, i.e. the XOR of three inputs.
+    Sum,
+    /// The carry bit of a full adder, i.e. the majority of three inputs.
+    Carry,
+}
+
+impl Display for GateKind {
+    /// Writes the name of the gate.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let repr = match self {
+            GateKind::Constant(false) => "zero",
+            GateKind::Constant(true) => "one",
+            GateKind::Buffer => "buf",
+            GateKind::Not => "not",
+            GateKind::And => "and",
+            GateKind::Or => "or",
+            GateKind::Xor => "xor",
+            GateKind::Nand => "nand",
+            GateKind::Nor => "nor",
+            GateKind::Xnor => "xnor",
+            GateKind::Mux => "mux",
+            GateKind::Sum => "sum",
+            GateKind::Carry => "carry",
+        };
+        write!(f, "{}", repr)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gate {
+    kind: GateKind,
+    inputs: Vec<Bit>,
+}
+
+impl Gate {
+    /// Creates a new gate.
+    pub fn new(kind: GateKind, inputs: Vec<Bit>) -> Gate {
+        Gate { kind, inputs }
+    }
+
+    /// Returns the kind of the gate.
+    pub fn kind(&self) -> GateKind {
+        self.kind
+    }
+
+    /// Returns the inputs of the gate. For a mux these are the select bit,
+    /// then the bit selected when it is set, then the bit selected otherwise.
+    pub fn inputs(&self) -> &Vec<Bit> {
+        &self.inputs
+    }
+}
+
+impl Display for Gate {
+    /// Writes the gate as a call, e.g. `and(param_0, param_1)`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let GateKind::Constant(_) = self.kind {
+            return write!(f, "{}", self.kind);
+        }
+        let inputs: Vec<String> = self.inputs.iter().map(|bit| bit.to_string()).collect();
+        write!(f, "{}({})", self.kind, inputs.join(", "))
+    }
+}
+
+/// How an output of a function was recognised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputGate {
+    /// The same gate is applied to both lanes independently.
+    Bitwise(Gate),
+    /// Each lane is computed by a different gate, which may read either lane
+    /// of its inputs. Lanes which are not a known primitive are `None`.
+    Lanes([Option<Gate>; 2]),
+}
+
+impl Display for OutputGate {
+    /// Writes the gate, or the gate of each lane.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputGate::Bitwise(gate) => write!(f, "{gate}"),
+            OutputGate::Lanes(gates) => {
+                let lanes: Vec<String> = Lane::all()
+                    .iter()
+                    .zip(gates)
+                    .map(|(lane, gate)| match gate {
+                        Some(gate) => format!("{lane}: {gate}"),
+                        None => format!("{lane}: ?"),
+                    })
+                    .collect();
+                write!(f, "{{ {} }}", lanes.join(", "))
+            }
+        }
+    }
+}
+
+/// Recognises the gate computed by each output of a truth table, with the
+/// top of the stack last. Outputs are decoded with `f2b`, as the VM does.
+pub fn recognise(table: &TruthTable) -> Vec<OutputGate> {
+    (0..table.num_outputs())
+        .map(|output| recognise_output(table, output))
+        .collect()
+}
+
+/// Returns the name of the gate a function implements, if all of its outputs
+/// were recognised as bitwise gates. A function which outputs the carry and
+/// sum of the same inputs, in either order, is a full adder.
+pub fn gate_name(gates: &[OutputGate]) -> Option<String> {
+    let bitwise: Vec<&Gate> = gates
+        .iter()
+        .map(|gate| match gate {
+            OutputGate::Bitwise(gate) => Some(gate),
+            OutputGate::Lanes(_) => None,
+        })
+        .collect::<Option<Vec<&Gate>>>()?;
+
+    match bitwise.as_slice() {
+        [gate] => Some(gate.kind().to_string()),
+        [first, second]
+            if matches!(
+                (first.kind(), second.kind()),
+                (GateKind::Carry, GateKind::Sum) | (GateKind::Sum, GateKind::Carry)
+            ) && first.inputs() == second.inputs() =>
+        {
+            Some(String::from("full_adder"))
+        }
+        _ => None,
+    }
+}
+
+/// Recognises the gate computed by a single output of a truth table.
+fn recognise_output(table: &TruthTable, output: usize) -> OutputGate {
+    let num_inputs = table.inputs().len();
+    let codes: Vec<(Vec<u8>, u8)> = table
+        .rows()
+        .iter()
+        .map(|row| {
+            (
+                row.inputs().iter().map(|value| f2b(*value)).collect(),
+                f2b(row.outputs()[output]),
+            )
+        })
+        .collect();
+
+    // a bitwise gate has the same function on each lane, reading only that
+    // lane of its inputs
+    let lane_tables: Vec<Option<Vec<bool>>> = Lane::all()
+        .iter()
+        .map(|lane| {
+            let mut function = vec![None; 1 << num_inputs];
+            for (inputs, result) in &codes {
+                let index = inputs.iter().enumerate().fold(0, |index, (i, code)| {
+                    index | (lane.bit(*code) as usize) << i
+                });
+                match function[index] {
+                    Some(bit) if bit != lane.bit(*result) => return None,
+                    _ => function[index] = Some(lane.bit(*result)),
+                }
+            }
+            function.into_iter().collect()
+        })
+        .collect();
+
+    if let [Some(infinite), Some(sign)] = lane_tables.as_slice() {
+        if infinite == sign {
+            if let Some((kind, vars)) = classify(infinite, num_inputs) {
+                let inputs = vars
+                    .iter()
+                    .map(|var| Bit::new(table.inputs()[*var], None))
+                    .collect();
+                return OutputGate::Bitwise(Gate::new(kind, inputs));
+            }
+        }
+    }
+
+    // otherwise, each bit of each input is a separate variable
+    let gates = Lane::all().map(|lane| {
+        let mut function = vec![false; 1 << (2 * num_inputs)];
+        for (inputs, result) in &codes {
+            let index = inputs
+                .iter()
+                .enumerate()
+                .fold(0, |index, (i, code)| index | (*code as usize) << (2 * i));
+            function[index] = lane.bit(*result);
+        }
+        classify(&function, 2 * num_inputs).map(|(kind, vars)| {
+            let inputs = vars
+                .iter()
+                .map(|var| Bit::new(table.inputs()[var / 2], Some(Lane::all()[var % 2])))
+                .collect();
+            Gate::new(kind, inputs)
+        })
+    });
+    OutputGate::Lanes(gates)
+}
+
+/// Matches a boolean function against the known primitives. Bit `i` of an
+/// index into the function is the value of variable `i`. Returns the kind of
+/// gate and the variables it reads, in the order the gate takes them.
+pub fn classify(function: &[bool], num_vars: usize) -> Option<(GateKind, Vec<usize>)> {
+    let support: Vec<usize> = (0..num_vars)
+        .filter(|var| {
+            (0..function.len()).any(|index| function[index] != function[index ^ (1 << var)])
+        })
+        .collect();
+
+    // restrict the function to the variables it depends on
+    let reduced: Vec<bool> = (0..1usize << support.len())
+        .map(|j| {
+            let index = support
+                .iter()
+                .enumerate()
+                .fold(0, |index, (i, var)| index | ((j >> i) & 1) << var);
+            function[index]
+        })
+        .collect();
+
+    let kind = match (support.len(), reduced.as_slice()) {
+        (0, [value]) => GateKind::Constant(*value),
+        (1, [false, true]) => GateKind::Buffer,
+        (1, [true, false]) => GateKind::Not,
+        (2, [false, false, false, true]) => GateKind::And,
+        (2, [false, true, true, true]) => GateKind::Or,
+        (2, [false, true, true, false]) => GateKind::Xor,
+        (2, [true, true, true, false]) => GateKind::Nand,
+        (2, [true, false, false, false]) => GateKind::Nor,
+        (2, [true, false, false, true]) => GateKind::Xnor,
+        (3, _) => return classify_ternary(&reduced, &support),
+        _ => return None,
+    };
+    Some((kind, support))
+}
+
+/// Matches a function of exactly three variables against the full adder and
+/// every assignment of the variables to a mux.
+fn classify_ternary(function: &[bool], vars: &[usize]) -> Option<(GateKind, Vec<usize>)> {
+    if (0..8).all(|j: usize| function[j] == (j.count_ones() % 2 == 1)) {
+        return Some((GateKind::Sum, vars.to_vec()));
+    }
+    if (0..8).all(|j: usize| function[j] == (j.count_ones() >= 2)) {
+        return Some((GateKind::Carry, vars.to_vec()));
+    }
+
+    for select in 0..3 {
+        for when_set in 0..3 {
+            let when_clear = 3 - select - when_set;
+            if when_set == select || when_clear == select {
+                continue;
+            }
+            let is_mux = (0..8).all(|j: usize| {
+                let chosen = if (j >> select) & 1 == 1 {
+                    when_set
+                } else {
+                    when_clear
+                };
+                function[j] == ((j >> chosen) & 1 == 1)
+            });
+            if is_mux {
+                return Some((
+                    GateKind::Mux,
+                    vec![vars[select], vars[when_set], vars[when_clear]],
+                ));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{gate_name, recognise, Bit, Gate, GateKind, OutputGate, MAX_GATE_INPUTS};
+    use crate::{
+        analysis::truth_table::{Input, TruthTable},
+        disassembler::disassembler::Disassembler,
+        emulator::dialect::VmDialect,
+    };
+
+    #[test]
+    fn recognises_g1_to_g4() {
+        let bytecode = fs::read_to_string("input/bytecode.txt").unwrap();
+        let mut disassembler = Disassembler::new(bytecode);
+        disassembler.disassemble().unwrap();
+        let stack_effects = disassembler.infer_stack_effects().unwrap();
+
+        for (id, name) in [("g1", "nand"), ("g2", "and"), ("g3", "or"), ("g4", "xor")] {
+            let function = disassembler
+                .functions()
+                .iter()
+                .find(|func| func.id() == id)
+                .unwrap();
+            let table = TruthTable::build(
+                function,
+                disassembler.functions(),
+                &stack_effects,
+                VmDialect::default(),
+            )
+            .unwrap();
+
+            let gates = recognise(&table);
+            assert_eq!(gates.len(), 1);
+            assert_eq!(gates[0].to_string(), format!("{name}(param_0, param_1)"));
+            assert_eq!(gate_name(&gates).as_deref(), Some(name));
+        }
+    }
+
+    #[test]
+    fn wide_functions_are_not_enumerated() {
+        // f reads 4 inputs, so its table of 256 rows is never built
+        let mut disassembler = Disassembler::new(String::from(":f:2345+++;:g:23+;"));
+        disassembler.disassemble().unwrap();
+        let stack_effects = disassembler.infer_stack_effects().unwrap();
+        let [f, g] = disassembler.functions().as_slice() else {
+            panic!("expected two functions");
+        };
+
+        let error = TruthTable::build_within(
+            f,
+            disassembler.functions(),
+            &stack_effects,
+            VmDialect::default(),
+            MAX_GATE_INPUTS,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "func_f has 4 inputs, more than the maximum of 3"
+        );
+        assert!(TruthTable::build_within(
+            g,
+            disassembler.functions(),
+            &stack_effects,
+            VmDialect::default(),
+            MAX_GATE_INPUTS,
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn full_adder_outputs_in_either_order() {
+        let inputs: Vec<Bit> = (0..3)
+            .map(|i| Bit::new(Input::Parameter(i), None))
+            .collect();
+        let carry = OutputGate::Bitwise(Gate::new(GateKind::Carry, inputs.clone()));
+        let sum = OutputGate::Bitwise(Gate::new(GateKind::Sum, inputs.clone()));
+        let full_adder = Some(String::from("full_adder"));
+
+        assert_eq!(gate_name(&[carry.clone(), sum.clone()]), full_adder);
+        assert_eq!(gate_name(&[sum.clone(), carry.clone()]), full_adder);
+        assert_eq!(gate_name(&[sum.clone(), sum]), None);
+
+        // the carry and sum must be of the same inputs
+        let other = OutputGate::Bitwise(Gate::new(GateKind::Sum, inputs[..2].to_vec()));
+        assert_eq!(gate_name(&[carry, other]), None);
+    }
+}
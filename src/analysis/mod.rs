@@ -0,0 +1,6 @@
+pub mod call_graph;
+pub mod circuit;
+pub mod gates;
+pub mod solver;
+pub mod truth_table;
+pub mod xrefs;
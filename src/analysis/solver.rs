@@ -0,0 +1,243 @@
+use std::{collections::HashMap, error::Error};
+
+use crate::{
+    disassembler::function::Function,
+    emulator::{
+        dialect::VmDialect,
+        encoding::f2b,
+        harness::{chain_values, chunk_args, Harness, CHAIN_START, CHAIN_VARIABLES},
+    },
+};
+
+use super::{
+    circuit::Circuit,
+    gates::{Bit, Lane},
+    truth_table::Input,
+};
+
+/// Number of values the harness pops off the stack: the checksum, the two
+/// encrypted bytes and the four bytes of the chain.
+const NUM_OUTPUT_VALUES: usize = 28;
+
+/// Number of words of candidates evaluated at once.
+const WORDS: usize = 16;
+
+/// Number of candidates evaluated at once.
+const BLOCK_SIZE: usize = 64 * WORDS;
+
+#[derive(Debug)]
+pub struct Solution {
+    plaintext: Vec<u8>,
+    candidates: Vec<Vec<[u8; 2]>>,
+}
+
+impl Solution {
+    /// Returns the recovered plaintext, using the first candidate for each
+    /// chunk.
+    pub fn plaintext(&self) -> &Vec<u8> {
+        &self.plaintext
+    }
+
+    /// Returns every plaintext which encrypts to each chunk, given the chain
+    /// left by the candidates used for the chunks before it.
+    pub fn candidates(&self) -> &Vec<Vec<[u8; 2]>> {
+        &self.candidates
+    }
+}
+
+/// Recovers the flag from its ciphertext. Each chunk of two bytes is brute
+/// forced against a circuit lifted from the algorithm, which evaluates many
+/// candidates at once, then checked on the emulator through the harness.
+#[derive(Debug)]
+pub struct Solver<'a> {
+    circuit: Circuit,
+    harness: Harness<'a>,
+}
+
+impl<'a> Solver<'a> {
+    /// Creates a new solver for the given algorithm, as run by a VM of the
+    /// given dialect.
+    pub fn new(
+        algorithm: &Function,
+        functions: &'a [Function],
+        dialect: VmDialect,
+    ) -> Result<Solver<'a>, Box<dyn Error>> {
+        let circuit = Circuit::lift_top_level(algorithm, functions, dialect)?;
+        if circuit.outputs().len() < NUM_OUTPUT_VALUES {
+            return Err(format!(
+                "func_{} leaves {} values on the stack, expected at least {NUM_OUTPUT_VALUES}",
+                algorithm.id(),
+                circuit.outputs().len()
+            )
+            .into());
+        }
+        let mut harness = Harness::new(functions, algorithm.id().to_string());
+        harness.set_dialect(dialect);
+        Ok(Solver { circuit, harness })
+    }
+
+    /// Recovers the plaintext of an encrypted flag, given the checksum of
+    /// each chunk. A flag of odd length ends with a byte which the harness
+    /// leaves unencrypted.
+    pub fn solve(
+        &mut self,
+        encrypted: &[u8],
+        checksums: &[u8],
+    ) -> Result<Solution, Box<dyn Error>> {
+        let mut plaintext = Vec::new();
+        let mut all_candidates = Vec::new();
+        let mut chain = CHAIN_START;
+
+        let chunks = encrypted.chunks_exact(2);
+        let unencrypted = chunks.remainder();
+        if chunks.len() != checksums.len() {
+            return Err(format!(
+                "Expected {} checksums for {} bytes, found {}",
+                chunks.len(),
+                encrypted.len(),
+                checksums.len()
+            )
+            .into());
+        }
+
+        for (i, (bytes, checksum)) in chunks.zip(checksums).enumerate() {
+            let target = [bytes[0], bytes[1], *checksum];
+            let candidates = self.solve_chunk(target, chain);
+            let chunk = *candidates
+                .first()
+                .ok_or(format!("No plaintext encrypts to chunk {i}"))?;
+
+            let encrypted_chunk = self.harness.encrypt_chunk(chunk, chain)?;
+            if encrypted_chunk.bytes() != [target[0], target[1]]
+                || encrypted_chunk.checksum() != target[2]
+            {
+                return Err(format!("Emulator disagrees with circuit for chunk {i}").into());
+            }
+            plaintext.extend(chunk);
+            all_candidates.push(candidates);
+            chain = encrypted_chunk.chain();
+        }
+        plaintext.extend(unencrypted);
+
+        Ok(Solution {
+            plaintext,
+            candidates: all_candidates,
+        })
+    }
+
+    /// Returns every pair of bytes which encrypts to the given bytes and
+    /// checksum under a chain.
+    pub fn solve_chunk(&self, target: [u8; 3], chain: u32) -> Vec<[u8; 2]> {
+        let mut fixed: HashMap<Bit, bool> = HashMap::new();
+        for (name, value) in CHAIN_VARIABLES.iter().zip(chain_values(chain)) {
+            for lane in Lane::all() {
+                let bit = Bit::new(Input::GlobalVariable(*name), Some(lane));
+                fixed.insert(bit, lane.bit(f2b(value)));
+            }
+        }
+        let circuit = self.circuit.specialise(&fixed);
+
+        let mut inputs: HashMap<Bit, [u64; WORDS]> = HashMap::new();
+        let mut candidates = Vec::new();
+        for block in 0..(1 << 16) / BLOCK_SIZE {
+            let chunk = |k: usize| {
+                let candidate = (block * BLOCK_SIZE + k) as u16;
+                candidate.to_be_bytes()
+            };
+            for param in 0..8 {
+                set_code(&mut inputs, Input::Parameter(param), |k| {
+                    f2b(chunk_args(chunk(k))[param])
+                });
+            }
+
+            let outputs = circuit.evaluate_sliced(&inputs);
+            let mut matches = [u64::MAX; WORDS];
+
+            // the checksum is on top, followed by the second byte then the
+            // first, with the least significant bits of each on top
+            for (depth, byte) in [target[2], target[1], target[0]].iter().enumerate() {
+                for digit in 0..4 {
+                    let lanes = outputs[outputs.len() - 1 - 4 * depth - digit];
+                    let code = (byte >> (2 * digit)) & 3;
+                    for (lane, words) in Lane::all().iter().zip(lanes) {
+                        let expected = if lane.bit(code) { u64::MAX } else { 0 };
+                        for (word, value) in matches.iter_mut().zip(words) {
+                            *word &= !(value ^ expected);
+                        }
+                    }
+                }
+            }
+
+            for k in (0..BLOCK_SIZE).filter(|k| (matches[k / 64] >> (k % 64)) & 1 == 1) {
+                candidates.push(chunk(k));
+            }
+        }
+        candidates
+    }
+}
+
+/// Sets the bits of both lanes of an input for every evaluation in a block,
+/// given the 2-bit code of the input in each.
+fn set_code(inputs: &mut HashMap<Bit, [u64; WORDS]>, input: Input, code: impl Fn(usize) -> u8) {
+    for lane in Lane::all() {
+        let mut value = [0; WORDS];
+        for k in (0..BLOCK_SIZE).filter(|k| lane.bit(code(*k))) {
+            value[k / 64] |= 1 << (k % 64);
+        }
+        inputs.insert(Bit::new(input, Some(lane)), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Solver;
+    use crate::{
+        disassembler::disassembler::Disassembler,
+        emulator::{
+            dialect::VmDialect,
+            harness::{parse_ciphertext, Harness, CHAIN_START},
+        },
+    };
+
+    /// An algorithm which leaves the chain, then the first byte with its
+    /// bits negated, then the second byte, then a checksum mixing the two.
+    const ALGORITHM: &str = "abcdefghijklnopq6!7!8!9!234526m37M4!59m";
+
+    #[test]
+    fn decrypts_known_plaintext() {
+        let mut disassembler = Disassembler::new(String::new());
+        disassembler
+            .disassemble_snippet(String::from("algorithm"), ALGORITHM.to_string())
+            .unwrap();
+        let functions = disassembler.functions();
+
+        // encrypt as the harness does, leaving the odd byte unencrypted
+        let plaintext = b"hey!\0";
+        let mut harness = Harness::new(functions, String::from("algorithm"));
+        let mut chain = CHAIN_START;
+        let mut encrypted = Vec::new();
+        let mut checksums = Vec::new();
+        for bytes in plaintext.chunks_exact(2) {
+            let chunk = harness.encrypt_chunk([bytes[0], bytes[1]], chain).unwrap();
+            encrypted.extend(chunk.bytes());
+            checksums.push(chunk.checksum());
+            chain = chunk.chain();
+        }
+        encrypted.push(plaintext[4]);
+        let ciphertext: String = encrypted
+            .iter()
+            .chain(&checksums)
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        assert_eq!(
+            parse_ciphertext(&ciphertext).unwrap(),
+            (encrypted, checksums)
+        );
+
+        let (encrypted, checksums) = parse_ciphertext(&ciphertext).unwrap();
+        let mut solver = Solver::new(&functions[0], functions, VmDialect::default()).unwrap();
+        let solution = solver.solve(&encrypted, &checksums).unwrap();
+        assert_eq!(solution.plaintext(), plaintext);
+        assert_eq!(solution.candidates(), &[vec![*b"he"], vec![*b"y!"]]);
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    fmt::Display,
+};
+
+use crate::{
+    disassembler::{
+        function::Function, instruction::Mnemonic, operand::Operand, stack_effect::StackEffect,
+    },
+    emulator::{
+        dialect::VmDialect,
+        emulator::{Emulator, NUM_ARGS},
+        encoding::{format_value, VALUES},
+    },
+};
+
+/// Maximum number of inputs which will be enumerated, giving 4^8 rows.
+pub const MAX_INPUTS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Input {
+    /// A parameter, which is also the stack slot at the same depth when the
+    /// function is called.
+    Parameter(usize),
+    GlobalVariable(char),
+}
+
+impl Display for Input {
+    /// Writes the name of the input.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Input::Parameter(index) => write!(f, "param_{index}"),
+            Input::GlobalVariable(name) => write!(f, "global_{name}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Row {
+    inputs: Vec<f32>,
+    outputs: Vec<f32>,
+}
+
+impl Row {
+    /// Returns the values of the inputs.
+    pub fn inputs(&self) -> &Vec<f32> {
+        &self.inputs
+    }
+
+    /// Returns the values the function leaves on the stack, with the top of
+    /// the stack last.
+    pub fn outputs(&self) -> &Vec<f32> {
+        &self.outputs
+    }
+}
+
+#[derive(Debug)]
+pub struct TruthTable {
+    function_id: String,
+    inputs: Vec<Input>,
+    num_outputs: usize,
+    rows: Vec<Row>,
+}
+
+impl TruthTable {
+    /// Builds the truth table of a function by evaluating it for every
+    /// combination of 0.0, -0.0, Infinity and -Infinity across its inputs.
+    pub fn build(
+        function: &Function,
+        functions: &[Function],
+        stack_effects: &HashMap<String, StackEffect>,
+        dialect: VmDialect,
+    ) -> Result<TruthTable, Box<dyn Error>> {
+        TruthTable::build_within(function, functions, stack_effects, dialect, MAX_INPUTS)
+    }
+
+    /// Builds the truth table of a function in the same way as `build`, but
+    /// fails without evaluating it if it has more than the given number of
+    /// inputs, since the table has 4^n rows.
+    pub fn build_within(
+        function: &Function,
+        functions: &[Function],
+        stack_effects: &HashMap<String, StackEffect>,
+        dialect: VmDialect,
+        max_inputs: usize,
+    ) -> Result<TruthTable, Box<dyn Error>> {
+        let effect = stack_effects
+            .get(function.id())
+            .ok_or(format!("Unknown stack effect for {}", function.id()))?;
+        let inputs = get_inputs(function, functions, effect)?;
+        if inputs.len() > max_inputs {
+            return Err(format!(
+                "func_{} has {} inputs, more than the maximum of {max_inputs}",
+                function.id(),
+                inputs.len()
+            )
+            .into());
+        }
+
+        let slots = effect.consumed().max(NUM_ARGS);
+        let mut emulator = Emulator::new(functions);
+        emulator.set_dialect(dialect);
+        let mut rows = Vec::new();
+
+        for index in 0..(1usize << (2 * inputs.len())) {
+            let values: Vec<f32> = (0..inputs.len())
+                .map(|i| VALUES[(index >> (2 * (inputs.len() - i - 1))) & 3])
+                .collect();
+
+            emulator.reset();
+            let mut stack = vec![0.0; slots];
+            for (input, value) in inputs.iter().zip(&values) {
+                match input {
+                    Input::Parameter(param) => stack[*param] = *value,
+                    Input::GlobalVariable(name) => emulator.set_variable(*name, *value),
+                }
+            }
+
+            // the top of the stack is copied into the arguments
+            let mut args = [0.0; NUM_ARGS];
+            args.copy_from_slice(&stack[..NUM_ARGS]);
+            for value in stack.iter().rev() {
+                emulator.push(*value)?;
+            }
+            emulator.run(function.id(), &args)?;
+
+            let result = emulator.stack();
+            if result.len() != slots - effect.consumed() + effect.produced() {
+                return Err(
+                    format!("Unexpected stack size after running func_{}", function.id()).into(),
+                );
+            }
+            rows.push(Row {
+                inputs: values,
+                outputs: result[result.len() - effect.produced()..].to_vec(),
+            });
+        }
+
+        Ok(TruthTable {
+            function_id: function.id().to_string(),
+            inputs,
+            num_outputs: effect.produced(),
+            rows,
+        })
+    }
+
+    /// Returns the ID of the function.
+    pub fn function_id(&self) -> &str {
+        &self.function_id
+    }
+
+    /// Returns the inputs, in the order their values appear in each row.
+    pub fn inputs(&self) -> &Vec<Input> {
+        &self.inputs
+    }
+
+    /// Returns the number of values the function leaves on the stack.
+    pub fn num_outputs(&self) -> usize {
+        self.num_outputs
+    }
+
+    /// Returns the rows of the table.
+    pub fn rows(&self) -> &Vec<Row> {
+        &self.rows
+    }
+}
+
+impl Display for TruthTable {
+    /// Writes the table with a column per input and output.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut header: Vec<String> = self.inputs.iter().map(|input| input.to_string()).collect();
+        header.push(String::from("|"));
+        header.extend((0..self.num_outputs).map(|i| format!("out_{i}")));
+        writeln!(f, "truth table of func_{}", self.function_id)?;
+        writeln!(f, "{}", format_columns(&header))?;
+
+        for row in &self.rows {
+            let mut columns: Vec<String> = row.inputs.iter().map(|v| format_value(*v)).collect();
+            columns.push(String::from("|"));
+            columns.extend(row.outputs.iter().map(|v| format_value(*v)));
+            writeln!(f, "{}", format_columns(&columns))?;
+        }
+        Ok(())
+    }
+}
+
+/// Pads columns to a fixed width, apart from separators.
+fn format_columns(columns: &[String]) -> String {
+    columns
+        .iter()
+        .map(|column| {
+            if column == "|" {
+                String::from("| ")
+            } else {
+                format!("{:<10}", column)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("")
+        .trim_end()
+        .to_string()
+}
+
+/// Returns the inputs of a function. These are the parameters it pushes
+/// and the stack slots it pops, along with every global variable read by it
+/// or the functions it calls.
+fn get_inputs(
+    function: &Function,
+    functions: &[Function],
+    effect: &StackEffect,
+) -> Result<Vec<Input>, Box<dyn Error>> {
+    let functions_by_id: HashMap<&str, &Function> =
+        functions.iter().map(|func| (func.id(), func)).collect();
+
+    let mut inputs: BTreeSet<Input> = (0..effect.consumed()).map(Input::Parameter).collect();
+    for instr in function.all_instructions() {
+        if let Some(Operand::Parameter(param)) = instr.operands().first() {
+            inputs.insert(Input::Parameter(param.index()));
+        }
+    }
+
+    let mut visited = BTreeSet::new();
+    let mut worklist = vec![function];
+    while let Some(func) = worklist.pop() {
+        if !visited.insert(func.id()) {
+            continue;
+        }
+        for instr in func.all_instructions() {
+            match (instr.mnemonic(), instr.operands().first()) {
+                (Mnemonic::PUSH, Some(Operand::GlobalVariable(var))) => {
+                    if let Some(name) = var.id().chars().next() {
+                        inputs.insert(Input::GlobalVariable(name));
+                    }
+                }
+                (Mnemonic::CALL, Some(Operand::FunctionReference(func_ref))) => {
+                    let callee = functions_by_id
+                        .get(func_ref.id())
+                        .ok_or(format!("Unknown callee {}", func_ref.to_string()))?;
+                    worklist.push(callee);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(inputs.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{Input, TruthTable};
+    use crate::{disassembler::disassembler::Disassembler, emulator::dialect::VmDialect};
+
+    #[test]
+    fn g1_table_matches_vm() {
+        let bytecode = fs::read_to_string("input/bytecode.txt").unwrap();
+        let mut disassembler = Disassembler::new(bytecode);
+        disassembler.disassemble().unwrap();
+        let stack_effects = disassembler.infer_stack_effects().unwrap();
+        let function = disassembler
+            .functions()
+            .iter()
+            .find(|func| func.id() == "g1")
+            .unwrap();
+
+        let table = TruthTable::build(
+            function,
+            disassembler.functions(),
+            &stack_effects,
+            VmDialect::default(),
+        )
+        .unwrap();
+        assert_eq!(table.inputs(), &[Input::Parameter(0), Input::Parameter(1)]);
+        assert_eq!(table.num_outputs(), 1);
+
+        // outputs of beyond.c for param_0 and param_1 each taking 0.0,
+        // Infinity, -0.0 and -Infinity in turn
+        let (inf, zero) = (f32::INFINITY, 0.0f32);
+        let expected = [
+            -inf, -inf, -inf, -inf, //
+            -inf, -zero, -inf, -zero, //
+            -inf, -inf, inf, inf, //
+            -inf, -zero, inf, zero,
+        ];
+        let outputs: Vec<u32> = table
+            .rows()
+            .iter()
+            .map(|row| row.outputs()[0].to_bits())
+            .collect();
+        let expected: Vec<u32> = expected.iter().map(|value| value.to_bits()).collect();
+        assert_eq!(outputs, expected);
+    }
+}
@@ -0,0 +1,211 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::disassembler::{
+    function::Function,
+    instruction::{Instruction, Mnemonic},
+    operand::{Operand, Variable},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XrefKind {
+    Call,
+    Push,
+    Store,
+}
+
+impl Display for XrefKind {
+    /// Writes the kind of reference as the mnemonic which makes it.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // padded, so that reports line up like the disassembly
+        f.pad(match self {
+            XrefKind::Call => "call",
+            XrefKind::Push => "push",
+            XrefKind::Store => "store",
+        })
+    }
+}
+
+/// An instruction which refers to a function or variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Xref {
+    function: String,
+    function_name: String,
+    address: usize,
+    kind: XrefKind,
+}
+
+impl Xref {
+    /// Returns the ID of the function containing the instruction.
+    pub fn function(&self) -> &str {
+        &self.function
+    }
+
+    /// Returns the name of the function containing the instruction, such as
+    /// `func_g3`.
+    pub fn function_name(&self) -> &str {
+        &self.function_name
+    }
+
+    /// Returns the address of the instruction.
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    /// Returns how the instruction refers to its operand.
+    pub fn kind(&self) -> XrefKind {
+        self.kind
+    }
+}
+
+/// Index from the name of each function, global variable and parameter to
+/// the instructions which refer to it. Parameters are named the same in
+/// every function, so they are keyed by the function as well, such as
+/// `func_g3.param_0`.
+#[derive(Debug)]
+pub struct Xrefs {
+    references: BTreeMap<String, Vec<Xref>>,
+}
+
+impl Xrefs {
+    /// Builds the index over the given functions. References to each name
+    /// are in the order of the functions, then of their addresses.
+    pub fn build(functions: &[Function]) -> Xrefs {
+        let mut references: BTreeMap<String, Vec<Xref>> = BTreeMap::new();
+        for function in functions {
+            for instr in function.all_instructions() {
+                if let Some((name, kind)) = reference(function, instr) {
+                    references.entry(name).or_default().push(Xref {
+                        function: function.id().to_string(),
+                        function_name: function.name().to_string(),
+                        address: *instr.address(),
+                        kind,
+                    });
+                }
+            }
+        }
+        Xrefs { references }
+    }
+
+    /// Returns the names which are referred to, in sorted order.
+    pub fn names(&self) -> Vec<&str> {
+        self.references.keys().map(String::as_str).collect()
+    }
+
+    /// Returns the references to a name, such as `func_g3`, `global_a` or
+    /// `func_g3.param_0`.
+    pub fn get(&self, name: &str) -> &[Xref] {
+        self.references.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the call sites of the function with the given ID.
+    pub fn calls_to(&self, id: &str) -> &[Xref] {
+        self.get(&format!("func_{id}"))
+    }
+
+    /// Writes the references to the given names, or to every name if none
+    /// are given.
+    pub fn report(&self, names: &[String]) -> String {
+        let mut lines = Vec::new();
+        for (name, xrefs) in &self.references {
+            if !names.is_empty() && !names.contains(name) {
+                continue;
+            }
+            let plural = if xrefs.len() == 1 { "" } else { "s" };
+            lines.push(format!("{name} ({} reference{plural})", xrefs.len()));
+            for xref in xrefs {
+                lines.push(format!(
+                    "\t0x{:x}:\t{:<12}in {}",
+                    xref.address, xref.kind, xref.function_name
+                ));
+            }
+            lines.push(String::new());
+        }
+        lines.join("\n")
+    }
+}
+
+impl Display for Xrefs {
+    /// Writes the references to every name.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.report(&[]))
+    }
+}
+
+/// Returns the name an instruction in a function refers to and how it does,
+/// if it refers to one. Calls are keyed by the ID of the callee, so that
+/// renaming functions does not split their references.
+fn reference(function: &Function, instr: &Instruction) -> Option<(String, XrefKind)> {
+    match (instr.mnemonic(), instr.operands().first()?) {
+        (Mnemonic::CALL, Operand::FunctionReference(func_ref)) => {
+            Some((format!("func_{}", func_ref.id()), XrefKind::Call))
+        }
+        (Mnemonic::PUSH, Operand::GlobalVariable(var)) => {
+            Some((var.name().to_string(), XrefKind::Push))
+        }
+        (Mnemonic::PUSH, Operand::Parameter(param)) => Some((
+            format!("{}.{}", function.name(), param.name()),
+            XrefKind::Push,
+        )),
+        (Mnemonic::STORE, Operand::GlobalVariable(var)) => {
+            Some((var.name().to_string(), XrefKind::Store))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{XrefKind, Xrefs};
+    use crate::disassembler::disassembler::Disassembler;
+
+    /// Builds the index over some bytecode.
+    fn build(bytecode: &str) -> Xrefs {
+        let mut disassembler = Disassembler::new(bytecode.to_string());
+        disassembler.disassemble().unwrap();
+        Xrefs::build(disassembler.functions())
+    }
+
+    #[test]
+    fn references_are_indexed_by_name() {
+        let xrefs = build(":f:2b=b;;:g:2^f;");
+        assert_eq!(
+            xrefs.names(),
+            ["func_f", "func_f.param_0", "func_g.param_0", "global_b"]
+        );
+
+        let stores: Vec<(&str, XrefKind)> = xrefs
+            .get("global_b")
+            .iter()
+            .map(|xref| (xref.function(), xref.kind()))
+            .collect();
+        assert_eq!(stores, [("f", XrefKind::Store), ("f", XrefKind::Push)]);
+
+        let calls = xrefs.calls_to("f");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].function_name(), "func_g");
+        assert_eq!(calls[0].kind(), XrefKind::Call);
+        assert!(xrefs.get("func_g").is_empty());
+    }
+
+    #[test]
+    fn parameters_are_kept_per_function() {
+        let xrefs = build(":f:22;:g:2;");
+        assert_eq!(xrefs.get("func_f.param_0").len(), 2);
+        assert_eq!(xrefs.get("func_g.param_0").len(), 1);
+        assert!(xrefs.get("param_0").is_empty());
+    }
+
+    #[test]
+    fn report_lists_requested_names() {
+        let xrefs = build(":f:2b=b;;:g:2^f;");
+        let call = xrefs.calls_to("f")[0].address();
+        assert_eq!(
+            xrefs.report(&["func_f".to_string()]),
+            format!("func_f (1 reference)\n\t0x{call:x}:\tcall        in func_g\n")
+        );
+        assert_eq!(xrefs.report(&["unknown".to_string()]), "");
+        let report = xrefs.to_string();
+        assert_eq!(report.matches(" reference").count(), 4);
+        assert!(report.contains("global_b (2 references)"));
+    }
+}
@@ -0,0 +1,397 @@
+use std::error::Error;
+
+/// Prefix of function names in the disassembly which is not part of the
+/// name used in the bytecode.
+const FUNCTION_PREFIX: &str = "func_";
+
+/// Prefix of global variable names in the disassembly.
+const GLOBAL_PREFIX: &str = "global_";
+
+/// Prefix of parameter names in the disassembly.
+const PARAMETER_PREFIX: &str = "param_";
+
+#[derive(Debug)]
+pub struct AssembledFunction {
+    name: String,
+    body: String,
+}
+
+impl AssembledFunction {
+    /// Returns the name of the function, as used by calls in the bytecode.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the bytecode of the body of the function, including the
+    /// delimiter which returns from it.
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    /// Returns the bytecode which defines the function.
+    pub fn definition(&self) -> String {
+        format!(":{}:{}", self.name, self.body)
+    }
+}
+
+/// A call which has been assembled, to check that the VM resolves it to the
+/// intended function.
+#[derive(Debug)]
+struct Call {
+    line: usize,
+    function_index: usize,
+    offset: usize,
+    callee: String,
+}
+
+#[derive(Debug)]
+pub struct Assembler {
+    listing: Vec<String>,
+    leading: String,
+    functions: Vec<AssembledFunction>,
+    calls: Vec<Call>,
+}
+
+impl Assembler {
+    /// Creates a new assembler for a listing in the format written to
+    /// `output/disassembly.txt`.
+    pub fn new(listing: String) -> Assembler {
+        Assembler {
+            listing: listing.lines().map(String::from).collect(),
+            leading: String::new(),
+            functions: Vec::new(),
+            calls: Vec::new(),
+        }
+    }
+
+    /// Returns the assembled functions, in the order they are defined.
+    pub fn functions(&self) -> &Vec<AssembledFunction> {
+        &self.functions
+    }
+
+    /// Returns the bytecode defining every function except the given
+    /// snippets, which are run on their own rather than defined. Bytes
+    /// before the first function come first.
+    pub fn get_bytecode(&self, snippets: &[&str]) -> String {
+        let definitions: String = self
+            .functions
+            .iter()
+            .filter(|func| !snippets.contains(&func.name()))
+            .map(|func| func.definition())
+            .collect();
+        format!("{}{definitions}", self.leading)
+    }
+
+    /// Returns the bytecode of a snippet, i.e. the body of a function.
+    pub fn get_snippet(&self, name: &str) -> Option<&str> {
+        self.functions
+            .iter()
+            .find(|func| func.name() == name)
+            .map(|func| func.body())
+    }
+
+    /// Assembles the listing. Addresses and comments in the listing are
+    /// ignored, so instructions can be freely added and removed.
+    pub fn assemble(&mut self) -> Result<(), Box<dyn Error>> {
+        // stores which have not been ended, by their indentation
+        let mut stores: Vec<usize> = Vec::new();
+
+        for index in 0..self.listing.len() {
+            let line_number = index + 1;
+            let line = self.listing[index].clone();
+            let code = match line.split_once("\t;") {
+                Some((code, _)) => code,
+                None => line.as_str(),
+            };
+            if code.trim().is_empty() {
+                continue;
+            }
+
+            if let Some(header) = code.strip_prefix("function ") {
+                // stores still open at the end of a function are ended there
+                while stores.pop().is_some() {
+                    self.emit(";", line_number)?;
+                }
+                let name = header
+                    .split_once('(')
+                    .map_or(header, |(name, _)| name)
+                    .trim();
+                self.functions.push(AssembledFunction {
+                    name: function_name(name, line_number)?,
+                    body: String::new(),
+                });
+                continue;
+            }
+
+            let indent = code.chars().take_while(|c| *c == '\t').count();
+            let (mnemonic, operand) = parse_instruction(code.trim(), line_number)?;
+
+            // a line indented less than a store's block ends the store
+            while stores.last().is_some_and(|store| indent <= *store) {
+                stores.pop();
+                self.emit(";", line_number)?;
+            }
+
+            let bytes = match mnemonic {
+                "push" => encode_push(operand, line_number)?,
+                "pop" => String::from("."),
+                "add" => String::from("+"),
+                "sub" => String::from("-"),
+                "mul" => String::from("*"),
+                "div" => String::from("/"),
+                "min" => String::from("m"),
+                "max" => String::from("M"),
+                "frac" => String::from("'"),
+                "neg" => String::from("!"),
+                "ret" => String::from(";"),
+                "store" => {
+                    stores.push(indent);
+                    format!("{}=", encode_global(operand, line_number)?)
+                }
+                "call" => {
+                    let callee = function_name(operand, line_number)?;
+                    let function_index = self.current_function(line_number)?;
+                    self.calls.push(Call {
+                        line: line_number,
+                        function_index,
+                        offset: self.functions[function_index].body.len() + 1,
+                        callee: callee.clone(),
+                    });
+                    format!("^{callee}")
+                }
+                // bytes before the first function lead the bytecode
+                "bytes" if self.functions.is_empty() => {
+                    self.leading.push_str(&unescape(operand, line_number)?);
+                    continue;
+                }
+                // bytes outside of any function follow the one before them
+                "unknown" | "invalid" | "bytes" => unescape(operand, line_number)?,
+                mnemonic => {
+                    return Err(format!("line {line_number}: unknown mnemonic {mnemonic:?}").into())
+                }
+            };
+            self.emit(&bytes, line_number)?;
+        }
+
+        while stores.pop().is_some() {
+            self.emit(";", self.listing.len())?;
+        }
+        self.check_calls()
+    }
+
+    /// Returns the index of the function being assembled.
+    fn current_function(&self, line_number: usize) -> Result<usize, Box<dyn Error>> {
+        self.functions
+            .len()
+            .checked_sub(1)
+            .ok_or(format!("line {line_number}: instruction is outside of a function").into())
+    }
+
+    /// Appends bytecode to the function being assembled.
+    fn emit(&mut self, bytes: &str, line_number: usize) -> Result<(), Box<dyn Error>> {
+        let index = self.current_function(line_number)?;
+        self.functions[index].body.push_str(bytes);
+        Ok(())
+    }
+
+    /// Checks that every call resolves to the intended function. Like the
+    /// VM, this takes the first function in definition order whose name is
+    /// a prefix of the bytes following the call opcode.
+    fn check_calls(&self) -> Result<(), Box<dyn Error>> {
+        for call in &self.calls {
+            let following = &self.functions[call.function_index].body[call.offset..];
+            let resolved = self
+                .functions
+                .iter()
+                .map(|func| func.name())
+                .find(|name| following.starts_with(name));
+            match resolved {
+                Some(name) if name == call.callee => {}
+                Some(name) => {
+                    return Err(format!(
+                        "line {}: call to func_{} would resolve to func_{name} in the VM",
+                        call.line, call.callee
+                    )
+                    .into())
+                }
+                None => {
+                    return Err(
+                        format!("line {}: unknown callee func_{}", call.line, call.callee).into(),
+                    )
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Splits an instruction into its mnemonic and operand, dropping its
+/// address.
+fn parse_instruction(code: &str, line_number: usize) -> Result<(&str, &str), Box<dyn Error>> {
+    let code = match code.split_once(':') {
+        Some((address, rest)) if address.starts_with("0x") => rest,
+        _ => code,
+    };
+    let code = code.trim();
+    if code.is_empty() {
+        return Err(format!("line {line_number}: expected a mnemonic").into());
+    }
+    Ok(match code.split_once(char::is_whitespace) {
+        Some((mnemonic, operand)) => (mnemonic, operand.trim()),
+        None => (code, ""),
+    })
+}
+
+/// Returns the name a function is defined with in the bytecode, given its
+/// name in the listing.
+fn function_name(name: &str, line_number: usize) -> Result<String, Box<dyn Error>> {
+    let name = name.strip_prefix(FUNCTION_PREFIX).unwrap_or(name);
+    if name.is_empty() || name.contains(':') {
+        return Err(format!("line {line_number}: invalid function name {name:?}").into());
+    }
+    Ok(name.to_string())
+}
+
+/// Encodes the operand of a push.
+fn encode_push(operand: &str, line_number: usize) -> Result<String, Box<dyn Error>> {
+    match operand {
+        "0" => Ok(String::from("0")),
+        "Infinity" => Ok(String::from("1")),
+        _ if operand.starts_with(GLOBAL_PREFIX) => encode_global(operand, line_number),
+        _ => {
+            // parameters are pushed by the digits from 2
+            let index = operand
+                .strip_prefix(PARAMETER_PREFIX)
+                .and_then(|index| index.parse::<u32>().ok())
+                .filter(|index| *index < 8)
+                .ok_or(format!(
+                    "line {line_number}: invalid operand {operand:?} for push"
+                ))?;
+            Ok(char::from_digit(index + 2, 10)
+                .ok_or(format!("line {line_number}: invalid parameter {operand:?}"))?
+                .to_string())
+        }
+    }
+}
+
+/// Encodes a global variable, which is accessed by its own opcode.
+fn encode_global(operand: &str, line_number: usize) -> Result<String, Box<dyn Error>> {
+    let mut chars = operand.strip_prefix(GLOBAL_PREFIX).unwrap_or("").chars();
+    match (chars.next(), chars.next()) {
+        (Some(var), None) if var.is_ascii_alphabetic() && !['m', 'M', 'A'].contains(&var) => {
+            Ok(var.to_string())
+        }
+        _ => Err(format!("line {line_number}: invalid global variable {operand:?}").into()),
+    }
+}
+
+/// Parses the quoted and escaped bytes of an unknown or invalid instruction.
+fn unescape(operand: &str, line_number: usize) -> Result<String, Box<dyn Error>> {
+    let error = || format!("line {line_number}: invalid quoted bytes {operand:?}");
+    let quoted = operand
+        .strip_prefix('"')
+        .and_then(|operand| operand.strip_suffix('"'))
+        .ok_or_else(error)?;
+
+    let mut bytes = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            bytes.push(c);
+            continue;
+        }
+        let escaped = match chars.next().ok_or_else(error)? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            'u' => {
+                let code: String = chars.by_ref().skip(1).take_while(|c| *c != '}').collect();
+                u32::from_str_radix(&code, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(error)?
+            }
+            c => c,
+        };
+        bytes.push(escaped);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Assembler;
+    use crate::disassembler::disassembler::Disassembler;
+
+    /// Assembles a listing.
+    fn assemble(listing: &str) -> Result<Assembler, String> {
+        let mut assembler = Assembler::new(listing.to_string());
+        assembler.assemble().map_err(|e| e.to_string())?;
+        Ok(assembler)
+    }
+
+    #[test]
+    fn disassembly_assembles_to_shipped_bytecode() {
+        let bytecode = fs::read_to_string("input/bytecode.txt").unwrap();
+        let algo = fs::read_to_string("input/algo.txt").unwrap();
+        let mut disassembler = Disassembler::new(bytecode.clone());
+        disassembler.disassemble().unwrap();
+        disassembler
+            .disassemble_snippet(String::from("algorithm"), algo.clone())
+            .unwrap();
+
+        let assembler = assemble(&disassembler.get_disassembly().join("\n")).unwrap();
+        assert_eq!(assembler.get_bytecode(&["algorithm"]), bytecode);
+        assert_eq!(assembler.get_snippet("algorithm"), Some(algo.as_str()));
+    }
+
+    #[test]
+    fn leading_bytes_are_assembled_again() {
+        let bytecode = " #\n:f:2;\n:g:3;";
+        let mut disassembler = Disassembler::new(bytecode.to_string());
+        disassembler.disassemble().unwrap();
+        let listing = disassembler.get_disassembly();
+        assert_eq!(listing[0], "\tbytes       \" #\\n\"");
+
+        let assembler = assemble(&listing.join("\n")).unwrap();
+        assert_eq!(assembler.get_bytecode(&[]), bytecode);
+        assert_eq!(assembler.functions().len(), 2);
+    }
+
+    #[test]
+    fn call_resolving_elsewhere_is_rejected() {
+        let listing = "function func_a()\n\
+                       \tpush param_0\n\
+                       \tret\n\
+                       function func_ab()\n\
+                       \tpush param_1\n\
+                       \tret\n\
+                       function func_t()\n\
+                       \tcall func_ab\n\
+                       \tret\n";
+        let error = assemble(listing).unwrap_err();
+        assert_eq!(
+            error,
+            "line 8: call to func_ab would resolve to func_a in the VM"
+        );
+
+        // defining the longer name first resolves the call as written
+        let listing = "function func_ab()\n\
+                       \tpush param_1\n\
+                       \tret\n\
+                       function func_a()\n\
+                       \tpush param_0\n\
+                       \tret\n\
+                       function func_t()\n\
+                       \tcall func_ab\n\
+                       \tret\n";
+        let assembler = assemble(listing).unwrap();
+        assert_eq!(assembler.get_bytecode(&[]), ":ab:3;:a:2;:t:^ab;");
+
+        let error = assemble("function func_t()\n\tcall func_x\n").unwrap_err();
+        assert_eq!(error, "line 2: unknown callee func_x");
+    }
+}
@@ -0,0 +1,413 @@
+use std::{error::Error, fmt::Display};
+
+use beyond_vm_disassembler::emulator::dialect::{OperandOrder, VmDialect};
+
+/// Usage of the command line interface.
+pub const USAGE: &str = "\
+Usage: beyond-vm-disassembler <command> [options] [arguments...]
+
+Commands:
+  disasm [ids...]          write the disassembly of the given functions, or of every function
+  annotate [ids...]        print the disassembly annotated with stack depths
+  symex [ids...]           symbolically execute functions, reusing the summaries of callees
+        [--width <n>]        wrapping expressions longer than n columns
+        [--no-let]           without binding shared expressions to temporaries
+        [--calls <mode>]     modelling calls as summary, inline or opaque
+                             [default: summary]
+  emulate <id> [args...]   run a function on up to 8 arguments, e.g. 0, inf, -0 or -inf
+  truthtable [ids...]      print the truth tables of functions
+  gates                    report recognised gates on stderr and write the disassembly
+                           with them noted in comments
+  circuit <id> [--blif]    lift a function to a boolean circuit
+  solve [ciphertext]       recover the flag from its ciphertext
+  callgraph [--json]       export the call graph as Graphviz DOT, or as JSON
+  xrefs [names...]         list the references to functions and variables, e.g. func_g3, global_a or func_g3.param_0
+  assemble [listing]       turn a listing back into bytecode
+  help                     print this message
+
+Options:
+  --bytecode <path>        bytecode defining the functions [default: input/bytecode.txt]
+  --algo <path>            snippet run as the algorithm [default: input/algo.txt]
+  --no-algo                do not disassemble an algorithm snippet
+  -o, --output <path>      where to write the result, `-` for standard output
+                           [default: output/disassembly.txt for disasm and gates,
+                           output/func_<id>.blif for circuit --blif, the output
+                           directory for assemble, otherwise standard output]
+  --recover                keep disassembling after errors, reporting them instead
+  --operand-order <order>  which operand of a binary opcode the VM pops first,
+                           left-first or right-first [default: left-first, as
+                           the reference gcc build does]";
+
+/// Default path of the bytecode defining the functions.
+const DEFAULT_BYTECODE: &str = "input/bytecode.txt";
+
+/// Default path of the algorithm snippet.
+const DEFAULT_ALGO: &str = "input/algo.txt";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Disasm,
+    Annotate,
+    Symex,
+    Emulate,
+    TruthTable,
+    Gates,
+    Circuit,
+    Solve,
+    CallGraph,
+    Xrefs,
+    Assemble,
+    Help,
+}
+
+impl Command {
+    /// Parses the name of a command.
+    fn parse(name: &str) -> Option<Command> {
+        Some(match name {
+            "disasm" => Command::Disasm,
+            "annotate" => Command::Annotate,
+            "symex" => Command::Symex,
+            "emulate" => Command::Emulate,
+            "truthtable" => Command::TruthTable,
+            "gates" => Command::Gates,
+            "circuit" => Command::Circuit,
+            "solve" => Command::Solve,
+            "callgraph" => Command::CallGraph,
+            "xrefs" => Command::Xrefs,
+            "assemble" => Command::Assemble,
+            "help" | "-h" | "--help" => Command::Help,
+            _ => return None,
+        })
+    }
+
+    /// Returns whether the arguments of the command are IDs of the
+    /// functions it applies to.
+    pub fn takes_function_filter(&self) -> bool {
+        matches!(
+            self,
+            Command::Disasm | Command::Annotate | Command::Symex | Command::TruthTable
+        )
+    }
+
+    /// Returns whether the command cannot run without the stack effects of
+    /// the functions. Other commands fall back to assuming every argument is
+    /// a parameter if they cannot be inferred.
+    pub fn needs_stack_effects(&self) -> bool {
+        matches!(
+            self,
+            Command::Annotate
+                | Command::Symex
+                | Command::TruthTable
+                | Command::Gates
+                | Command::Circuit
+        )
+    }
+}
+
+impl Display for Command {
+    /// Formats the command as its name.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Command::Disasm => "disasm",
+            Command::Annotate => "annotate",
+            Command::Symex => "symex",
+            Command::Emulate => "emulate",
+            Command::TruthTable => "truthtable",
+            Command::Gates => "gates",
+            Command::Circuit => "circuit",
+            Command::Solve => "solve",
+            Command::CallGraph => "callgraph",
+            Command::Xrefs => "xrefs",
+            Command::Assemble => "assemble",
+            Command::Help => "help",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// How symbolic execution models calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Calls {
+    /// Calls apply the summary of the callee.
+    Summary,
+    /// Calls descend into the callee.
+    Inline,
+    /// Calls produce opaque call expressions.
+    Opaque,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    command: Command,
+    arguments: Vec<String>,
+    bytecode: String,
+    algo: Option<String>,
+    output: Option<String>,
+    recover: bool,
+    blif: bool,
+    json: bool,
+    width: Option<usize>,
+    bind_shared: bool,
+    calls: Calls,
+    dialect: VmDialect,
+}
+
+impl Options {
+    /// Parses the command line arguments, excluding the program name.
+    pub fn parse(args: &[String]) -> Result<Options, Box<dyn Error>> {
+        let mut args = args.iter();
+        let name = args.next().ok_or("Expected a command")?;
+        let command = Command::parse(name).ok_or(format!("Unknown command {name:?}"))?;
+
+        let mut options = Options {
+            command,
+            arguments: Vec::new(),
+            bytecode: String::from(DEFAULT_BYTECODE),
+            algo: Some(String::from(DEFAULT_ALGO)),
+            output: None,
+            recover: false,
+            blif: false,
+            json: false,
+            width: None,
+            bind_shared: true,
+            calls: Calls::Summary,
+            dialect: VmDialect::GCC,
+        };
+        while let Some(arg) = args.next() {
+            let mut value = |option: &str| {
+                args.next()
+                    .cloned()
+                    .ok_or(format!("Expected a value for {option}"))
+            };
+            match arg.as_str() {
+                "--bytecode" => options.bytecode = value(arg)?,
+                "--algo" => options.algo = Some(value(arg)?),
+                "--no-algo" => options.algo = None,
+                "-o" | "--output" => options.output = Some(value(arg)?),
+                "--recover" => options.recover = true,
+                "--operand-order" => {
+                    let order = match value(arg)?.as_str() {
+                        "left-first" => OperandOrder::LeftFirst,
+                        "right-first" => OperandOrder::RightFirst,
+                        order => return Err(format!("Unknown operand order {order:?}").into()),
+                    };
+                    options.dialect = VmDialect::new(order);
+                }
+                "--blif" if command == Command::Circuit => options.blif = true,
+                "--json" if command == Command::CallGraph => options.json = true,
+                "--width" if command == Command::Symex => {
+                    let width = value(arg)?;
+                    options.width = Some(
+                        width
+                            .parse()
+                            .map_err(|_| format!("Invalid width {width:?}"))?,
+                    );
+                }
+                "--no-let" if command == Command::Symex => options.bind_shared = false,
+                "--calls" if command == Command::Symex => {
+                    options.calls = match value(arg)?.as_str() {
+                        "summary" => Calls::Summary,
+                        "inline" => Calls::Inline,
+                        "opaque" => Calls::Opaque,
+                        calls => return Err(format!("Unknown call mode {calls:?}").into()),
+                    };
+                }
+                // `-` alone is a value, such as standard output
+                _ if arg.starts_with('-') && arg.len() > 1 && !is_value(arg) => {
+                    return Err(format!("Unknown option {arg:?} for {command}").into())
+                }
+                _ => options.arguments.push(arg.clone()),
+            }
+        }
+
+        let expected = match command {
+            Command::Emulate => 1..=9,
+            Command::Circuit => 1..=1,
+            Command::Solve | Command::Assemble => 0..=1,
+            Command::Gates | Command::CallGraph | Command::Help => 0..=0,
+            _ => 0..=usize::MAX,
+        };
+        if !expected.contains(&options.arguments.len()) {
+            return Err(format!("Wrong number of arguments for {command}").into());
+        }
+        Ok(options)
+    }
+
+    /// Returns the command to run.
+    pub fn command(&self) -> Command {
+        self.command
+    }
+
+    /// Returns the positional arguments of the command.
+    pub fn arguments(&self) -> &Vec<String> {
+        &self.arguments
+    }
+
+    /// Returns the path of the bytecode defining the functions.
+    pub fn bytecode(&self) -> &str {
+        &self.bytecode
+    }
+
+    /// Returns the path of the algorithm snippet, if one is disassembled.
+    pub fn algo(&self) -> Option<&str> {
+        self.algo.as_deref()
+    }
+
+    /// Returns where to write the result of the command, if not the default.
+    pub fn output(&self) -> Option<&str> {
+        self.output.as_deref()
+    }
+
+    /// Returns whether disassembly keeps going after errors.
+    pub fn recover(&self) -> bool {
+        self.recover
+    }
+
+    /// Returns the dialect of the VM which runs the bytecode.
+    pub fn dialect(&self) -> VmDialect {
+        self.dialect
+    }
+
+    /// Returns whether a circuit is exported as BLIF.
+    pub fn blif(&self) -> bool {
+        self.blif
+    }
+
+    /// Returns whether a call graph is exported as JSON rather than DOT.
+    pub fn json(&self) -> bool {
+        self.json
+    }
+
+    /// Returns the width expressions are wrapped at, if they are wrapped.
+    pub fn width(&self) -> Option<usize> {
+        self.width
+    }
+
+    /// Returns whether shared expressions are bound to temporaries.
+    pub fn bind_shared(&self) -> bool {
+        self.bind_shared
+    }
+
+    /// Returns how symbolic execution models calls.
+    pub fn calls(&self) -> Calls {
+        self.calls
+    }
+
+    /// Returns whether the command applies to the function with the given
+    /// ID. Commands which take a filter apply to every function if it is
+    /// empty.
+    pub fn selects(&self, id: &str) -> bool {
+        !self.command.takes_function_filter()
+            || self.arguments.is_empty()
+            || self.arguments.iter().any(|arg| arg == id)
+    }
+}
+
+/// Returns whether an argument starting with `-` is a value rather than an
+/// option, such as a negative argument to `emulate`.
+fn is_value(arg: &str) -> bool {
+    arg[1..].starts_with(|c: char| c.is_ascii_digit()) || arg == "-inf"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Calls, Command, OperandOrder, Options, VmDialect, USAGE};
+
+    /// Parses a command line given as a single string.
+    fn parse(args: &str) -> Result<Options, String> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        Options::parse(&args).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected() {
+        assert_eq!(
+            parse("disasm --frobnicate").unwrap_err(),
+            "Unknown option \"--frobnicate\" for disasm"
+        );
+        // options only apply to the commands which use them
+        assert_eq!(
+            parse("disasm --blif").unwrap_err(),
+            "Unknown option \"--blif\" for disasm"
+        );
+        assert!(parse("symex --calls inline").is_ok());
+        assert!(parse("disasm --calls inline").is_err());
+
+        // negative numbers are arguments rather than options
+        let options = parse("emulate g1 -0 -inf").unwrap();
+        assert_eq!(options.arguments(), &["g1", "-0", "-inf"]);
+        assert_eq!(
+            parse("frobnicate").unwrap_err(),
+            "Unknown command \"frobnicate\""
+        );
+    }
+
+    #[test]
+    fn missing_values_are_rejected() {
+        assert_eq!(parse("disasm -o").unwrap_err(), "Expected a value for -o");
+        assert_eq!(
+            parse("disasm --output").unwrap_err(),
+            "Expected a value for --output"
+        );
+
+        // `-` is a value, meaning standard output
+        assert_eq!(parse("disasm -o -").unwrap().output(), Some("-"));
+        assert_eq!(parse("disasm").unwrap().output(), None);
+    }
+
+    #[test]
+    fn operand_order_is_parsed() {
+        assert_eq!(parse("disasm").unwrap().dialect(), VmDialect::GCC);
+        assert_eq!(
+            parse("disasm --operand-order left-first")
+                .unwrap()
+                .dialect(),
+            VmDialect::new(OperandOrder::LeftFirst)
+        );
+        assert_eq!(
+            parse("disasm --operand-order right-first")
+                .unwrap()
+                .dialect(),
+            VmDialect::new(OperandOrder::RightFirst)
+        );
+        assert_eq!(
+            parse("disasm --operand-order sideways").unwrap_err(),
+            "Unknown operand order \"sideways\""
+        );
+        assert_eq!(
+            parse("disasm --operand-order").unwrap_err(),
+            "Expected a value for --operand-order"
+        );
+        assert_eq!(parse("symex").unwrap().calls(), Calls::Summary);
+    }
+
+    #[test]
+    fn help_lists_every_command() {
+        for args in ["help", "-h", "--help"] {
+            assert_eq!(parse(args).unwrap().command(), Command::Help);
+        }
+        assert!(parse("help disasm").is_err());
+
+        let commands = [
+            Command::Disasm,
+            Command::Annotate,
+            Command::Symex,
+            Command::Emulate,
+            Command::TruthTable,
+            Command::Gates,
+            Command::Circuit,
+            Command::Solve,
+            Command::CallGraph,
+            Command::Xrefs,
+            Command::Assemble,
+            Command::Help,
+        ];
+        for command in commands {
+            assert_eq!(Command::parse(&command.to_string()), Some(command));
+            assert!(
+                USAGE.contains(&format!("\n  {command} ")),
+                "{command} is not in the usage"
+            );
+        }
+    }
+}
@@ -1,15 +1,29 @@
-use std::{cmp::min, collections::HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
 
 use super::{
+    error::{DisassemblyError, DisassemblyWarning},
     function::Function,
     instruction::{Instruction, Mnemonic},
-    operand::{GlobalVariable, Literal, Operand, Parameter},
+    operand::{FunctionReference, GlobalVariable, Literal, Operand, Parameter, RawBytes},
+    stack_effect::{compute_stack_effects, StackEffect},
 };
 use lazy_static::lazy_static;
 
 lazy_static! {
+    // the VM also excludes 'A' from the variables, although it is not an
+    // opcode, so it is skipped like any other unknown byte
     static ref RESERVED_ALPHANUMERIC_OPCODES: HashSet<char> =
-        vec!['m', 'M', '0', '1'].into_iter().collect();
+        vec!['m', 'M', 'A', '0', '1'].into_iter().collect();
+}
+
+/// A piece of bytecode which has been disassembled, such as the bytecode
+/// defining the functions or a snippet run on its own.
+struct Source {
+    leading: String,
+    first_function: usize,
 }
 
 pub struct Disassembler {
@@ -20,6 +34,12 @@ pub struct Disassembler {
     current_function_index: Option<usize>,
     num_functions: usize,
     delimiter: char,
+    stores: Vec<Instruction>,
+    names: Vec<String>,
+    recover: bool,
+    diagnostics: Vec<DisassemblyError>,
+    warnings: Vec<DisassemblyWarning>,
+    sources: Vec<Source>,
 }
 
 impl Disassembler {
@@ -33,24 +53,79 @@ impl Disassembler {
             current_function_index: None,
             num_functions: 0,
             delimiter: ';',
+            stores: Vec::new(),
+            names: Vec::new(),
+            recover: false,
+            diagnostics: Vec::new(),
+            warnings: Vec::new(),
+            sources: Vec::new(),
         }
     }
 
+    /// Sets whether disassembly keeps going after errors. Errors are then
+    /// recorded as diagnostics and as `unknown` or `invalid` instructions,
+    /// and disassembly resumes at the next function or delimiter.
+    pub fn set_recover(&mut self, recover: bool) {
+        self.recover = recover;
+    }
+
+    /// Returns the errors recovered from so far.
+    pub fn diagnostics(&self) -> &Vec<DisassemblyError> {
+        &self.diagnostics
+    }
+
+    /// Returns the warnings found so far, which do not stop disassembly.
+    pub fn warnings(&self) -> &Vec<DisassemblyWarning> {
+        &self.warnings
+    }
+
     /// Returns the vector of functions.
-    pub fn functions(self) -> Vec<Function> {
-        self.functions
+    pub fn functions(&self) -> &Vec<Function> {
+        &self.functions
     }
 
-    /// Reads the next byte from the bytecode and advances the position.
-    fn read_byte(&mut self) -> char {
-        let byte = self.bytecode[self.pos];
+    /// Infers the stack effect of every function, storing it on the function.
+    /// Returns the stack effects keyed by function ID.
+    pub fn infer_stack_effects(&mut self) -> Result<HashMap<String, StackEffect>, Box<dyn Error>> {
+        let effects = compute_stack_effects(&self.functions)?;
+        for function in &mut self.functions {
+            if let Some(effect) = effects.get(function.id()) {
+                function.set_stack_effect(*effect);
+            }
+        }
+        Ok(effects)
+    }
+
+    /// Sets the descriptive name of the function with the given ID, and of
+    /// every call to it.
+    pub fn set_display_name(&mut self, id: &str, name: String) {
+        for function in &mut self.functions {
+            if function.id() == id {
+                function.set_display_name(name.clone());
+            }
+            function.set_reference_display_names(id, &name);
+        }
+    }
+
+    /// Reads the next byte from the bytecode and advances the position. The
+    /// context describes what is being read if the bytecode has ended.
+    fn read_byte(&mut self, context: &str) -> Result<char, DisassemblyError> {
+        let byte = self
+            .bytecode
+            .get(self.pos)
+            .copied()
+            .ok_or(DisassemblyError::UnexpectedEnd {
+                offset: self.base_address + self.pos,
+                context: context.to_string(),
+            })?;
         self.pos += 1;
-        return byte;
+        Ok(byte)
     }
 
     /// Reads the next byte from the bytecode, without advancing the position.
-    fn peek_byte(&self) -> char {
-        return self.bytecode[self.pos];
+    /// Returns `None` at the end of the bytecode.
+    fn peek_byte(&self) -> Option<char> {
+        self.bytecode.get(self.pos).copied()
     }
 
     /// Advances the position.
@@ -58,19 +133,48 @@ impl Disassembler {
         self.pos += 1;
     }
 
-    /// Adds an instruction to the current function being disassembled.
-    fn add_instruction(&mut self, instr: Instruction) {
+    /// Adds an instruction to the innermost store being disassembled, or
+    /// to the current function if there is none.
+    fn add_instruction(&mut self, instr: Instruction) -> Result<(), DisassemblyError> {
+        if let Some(store) = self.stores.last_mut() {
+            store.block_mut().push(instr);
+            return Ok(());
+        }
         match self.current_function_index {
-            Some(i) => self.functions[i].add_instruction(instr),
-            None => panic!("Tried to add an instruction when there is no current function"),
-        };
+            Some(i) => {
+                self.functions[i].add_instruction(instr);
+                Ok(())
+            }
+            None => Err(DisassemblyError::NoCurrentFunction {
+                offset: *instr.address(),
+                mnemonic: instr.mnemonic().to_string(),
+            }),
+        }
     }
 
-    /// Returns the disassembly as a vector of disassembly lines.
+    /// Returns the disassembly as a vector of disassembly lines. Bytes
+    /// before the first function of a piece of bytecode are written as a
+    /// `bytes` line of their own, so that they are assembled again.
     pub fn get_disassembly(&self) -> Vec<String> {
+        self.get_selected_disassembly(|_| true)
+    }
+
+    /// Returns the disassembly of the functions whose IDs are selected, in
+    /// the same way as `get_disassembly`. Bytes before a function are only
+    /// written if it is selected.
+    pub fn get_selected_disassembly(&self, selects: impl Fn(&str) -> bool) -> Vec<String> {
         let mut dis: Vec<String> = Vec::new();
 
-        for func in &self.functions {
+        for (i, func) in self.functions.iter().enumerate() {
+            if !selects(func.id()) {
+                continue;
+            }
+            for source in &self.sources {
+                if source.first_function == i && !source.leading.is_empty() {
+                    dis.push(format!("\t{:<12}{:?}", "bytes", source.leading));
+                    dis.push(String::from(""));
+                }
+            }
             dis.append(&mut func.get_disassembly());
             dis.push(String::from("")); // empty line to separate functions
         }
@@ -78,9 +182,33 @@ impl Disassembler {
         dis
     }
 
+    /// Encodes the disassembled functions as bytecode. Returns the bytecode
+    /// of each piece disassembled, i.e. the bytecode passed to the
+    /// disassembler followed by each snippet, which are reproduced exactly.
+    pub fn encode(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut encoded = Vec::new();
+        for (i, source) in self.sources.iter().enumerate() {
+            let end = self
+                .sources
+                .get(i + 1)
+                .map_or(self.functions.len(), |next| next.first_function);
+            let mut bytes = source.leading.clone();
+            for func in &self.functions[source.first_function..end] {
+                bytes.push_str(&func.encode()?);
+            }
+            encoded.push(bytes);
+        }
+        Ok(encoded)
+    }
+
     /// Disassembles a new snippet of bytecode.
-    pub fn disassemble_snippet(&mut self, snippet_name: String, bytecode: String) {
-        let func = Function::new(self.base_address + self.pos, snippet_name, 8);
+    pub fn disassemble_snippet(
+        &mut self,
+        snippet_name: String,
+        bytecode: String,
+    ) -> Result<(), DisassemblyError> {
+        self.begin_source();
+        let func = Function::new_snippet(self.base_address + self.pos, snippet_name);
         self.functions.push(func);
         self.current_function_index = Some(self.num_functions);
         self.num_functions += 1;
@@ -89,137 +217,401 @@ impl Disassembler {
         self.base_address += self.pos; // update base address
         self.pos = 0; // reset pos within bytecode
 
-        self.disassemble();
+        self.disassemble_bytecode()
     }
 
     /// Disassembles the current bytecode.
-    pub fn disassemble(&mut self) {
-        let bytecode_length = self.bytecode.len();
-        while self.pos < bytecode_length {
+    pub fn disassemble(&mut self) -> Result<(), DisassemblyError> {
+        self.begin_source();
+        self.disassemble_bytecode()
+    }
+
+    /// Starts a new piece of bytecode, whose functions are those created
+    /// from now on.
+    fn begin_source(&mut self) {
+        self.sources.push(Source {
+            leading: String::new(),
+            first_function: self.functions.len(),
+        });
+    }
+
+    /// Keeps bytes which are outside of any function, so that they are
+    /// encoded again. They belong after the last function of the current
+    /// piece of bytecode, or before its first if there is none yet.
+    fn add_trivia(&mut self, bytes: &str) {
+        let Some(source) = self.sources.last_mut() else {
+            return;
+        };
+        if self.functions.len() > source.first_function {
+            if let Some(func) = self.functions.last_mut() {
+                func.add_trailing(bytes);
+            }
+        } else {
+            source.leading.push_str(bytes);
+        }
+    }
+
+    /// Disassembles the rest of the current bytecode.
+    fn disassemble_bytecode(&mut self) -> Result<(), DisassemblyError> {
+        self.scan_names();
+        while self.pos < self.bytecode.len() {
             let address = self.base_address + self.pos;
-            let opcode = self.read_byte();
-
-            // delimiter means stop disassembling
-            if opcode == self.delimiter {
-                if let Some(_) = self.current_function_index {
-                    let instr = Instruction::new(address, Mnemonic::RET, Vec::new());
-                    self.add_instruction(instr);
-                    self.current_function_index = None;
+            let opcode = self.read_byte("opcode")?;
+            match self.disassemble_opcode(address, opcode) {
+                Ok(()) => {}
+                Err(e) if self.recover => self.resynchronise(address, e),
+                Err(e) => return Err(e),
+            }
+        }
+
+        // a store must be ended by a delimiter before the bytecode ends
+        if let Some(store) = self.stores.last() {
+            let variable = match store.operands().first() {
+                Some(Operand::GlobalVariable(var)) => var.id().chars().next(),
+                _ => None,
+            };
+            let error = DisassemblyError::UnterminatedStore {
+                offset: *store.address(),
+                variable: variable.unwrap_or('?'),
+            };
+            if !self.recover {
+                return Err(error);
+            }
+
+            // the stores are ended where the bytecode ends
+            self.diagnostics.push(error);
+            while let Some(mut store) = self.stores.pop() {
+                store.set_length(self.base_address + self.pos - store.address());
+                if let Err(e) = self.add_instruction(store) {
+                    self.diagnostics.push(e);
                 }
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds the names of the functions defined in the current bytecode, in
+    /// definition order. The VM defines every function before running any
+    /// of them, so calls can refer to functions defined after the caller.
+    fn scan_names(&mut self) {
+        let mut pos = self.pos;
+        while pos < self.bytecode.len() {
+            let byte = self.bytecode[pos];
+            pos += 1;
+            if byte != ':' {
                 continue;
             }
-            // push global variable or parameter
-            else if opcode.is_alphanumeric() && !RESERVED_ALPHANUMERIC_OPCODES.contains(&opcode) {
-                let next_byte = self.peek_byte();
-                if next_byte == '=' {
-                    self.advance();
-                    // this never appears in the sample
-                    panic!("Storing variables as result of subroutines not implemented")
-                }
 
-                // letters correspond to global variables
-                if opcode.is_alphabetic() {
-                    let var = Operand::GlobalVariable(GlobalVariable::new(opcode.to_string()));
-                    let instr = Instruction::new(address, Mnemonic::PUSH, vec![var]);
-                    self.add_instruction(instr);
-                }
-                // numbers correspond to parameters
-                else {
-                    let param_index = (opcode as usize) - 50;
-                    let param = Operand::Parameter(Parameter::new(param_index));
-                    let instr = Instruction::new(address, Mnemonic::PUSH, vec![param]);
-                    self.add_instruction(instr);
+            let Some(length) = self.bytecode[pos..].iter().position(|byte| *byte == ':') else {
+                break;
+            };
+            self.names
+                .push(self.bytecode[pos..pos + length].iter().collect());
+            pos += length + 1;
+
+            // skip the body, whose stores are nested up to matching delimiters
+            let mut nest = 1;
+            while nest > 0 && pos < self.bytecode.len() {
+                match self.bytecode[pos] {
+                    '=' => nest += 1,
+                    byte if byte == self.delimiter => nest -= 1,
+                    _ => {}
                 }
+                pos += 1;
             }
-            // create function
-            else if opcode == ':' {
-                let mut name = String::new();
-                loop {
-                    let next_byte = self.read_byte();
-                    if next_byte == ':' {
-                        break;
-                    } else {
-                        name.push(next_byte);
-                    }
-                }
+        }
+    }
 
-                let func = Function::new(address, name, 8);
-                self.functions.push(func);
-                self.current_function_index = Some(self.num_functions);
-                self.num_functions += 1;
-            }
-            // call function
-            else if opcode == '^' {
-                // Access next 10 chars as a lookahead buffer. Function IDs are <= 3
-                // chars so this is more than long enough
-                let next_bytecode: String = self.bytecode
-                    [self.pos..min(self.pos + 10, bytecode_length)]
-                    .iter()
-                    .collect();
-
-                // find which function is being called
-                let mut callee: Option<&Function> = None;
-                for func in &self.functions {
-                    if next_bytecode.starts_with(func.id()) {
-                        callee = Some(func);
-                    }
-                }
+    /// Records an error, along with an `invalid` instruction holding the
+    /// bytes from the opcode it was found at up to the next function or
+    /// delimiter, then skips to that point.
+    fn resynchronise(&mut self, address: usize, error: DisassemblyError) {
+        self.diagnostics.push(error);
+
+        let start = address - self.base_address;
+        let end = (start + 1..self.bytecode.len())
+            .find(|i| self.bytecode[*i] == ':' || self.bytecode[*i] == self.delimiter)
+            .unwrap_or(self.bytecode.len());
+        let bytes: String = self.bytecode[start..end].iter().collect();
+        self.pos = end;
+
+        let length = end - start;
+        let operands = vec![Operand::RawBytes(RawBytes::new(bytes.clone()))];
+        let mut instr = Instruction::new(address, Mnemonic::INVALID, operands);
+        instr.set_length(length);
+        // outside of a function there is nowhere to put the instruction, but
+        // the error has already been recorded
+        if self.add_instruction(instr).is_err() {
+            self.add_trivia(&bytes);
+        }
+    }
 
-                if let Some(func) = callee {
-                    let operands = vec![Operand::FunctionReference(func.get_reference())];
-                    let instr = Instruction::new(address, Mnemonic::CALL, operands);
-                    self.add_instruction(instr);
+    /// Disassembles a single opcode, reading any bytes which follow it.
+    fn disassemble_opcode(&mut self, address: usize, opcode: char) -> Result<(), DisassemblyError> {
+        // delimiter ends the innermost store, otherwise it means stop
+        // disassembling
+        if opcode == self.delimiter && !self.stores.is_empty() {
+            if let Some(mut store) = self.stores.pop() {
+                store.set_length(self.base_address + self.pos - store.address());
+                self.add_instruction(store)?;
+            }
+        } else if opcode == self.delimiter {
+            if self.current_function_index.is_some() {
+                let instr = Instruction::new(address, Mnemonic::RET, Vec::new());
+                self.add_instruction(instr)?;
+                self.current_function_index = None;
+            } else {
+                self.add_trivia(&opcode.to_string());
+            }
+        }
+        // push global variable or parameter
+        else if opcode.is_ascii_alphanumeric() && !RESERVED_ALPHANUMERIC_OPCODES.contains(&opcode)
+        {
+            // the block up to the matching delimiter is run, then the value
+            // it leaves on top of the stack is stored in the variable
+            if self.peek_byte() == Some('=') {
+                self.advance();
+                let var = Operand::GlobalVariable(GlobalVariable::new(opcode.to_string()));
+                let store = Instruction::new(address, Mnemonic::STORE, vec![var]);
+                self.stores.push(store);
+                return Ok(());
+            }
+
+            // letters correspond to global variables
+            if opcode.is_ascii_alphabetic() {
+                let var = Operand::GlobalVariable(GlobalVariable::new(opcode.to_string()));
+                let instr = Instruction::new(address, Mnemonic::PUSH, vec![var]);
+                self.add_instruction(instr)?;
+            }
+            // numbers correspond to parameters
+            else {
+                let param_index = (opcode as usize) - 50;
+                let param = Operand::Parameter(Parameter::new(param_index));
+                let instr = Instruction::new(address, Mnemonic::PUSH, vec![param]);
+                self.add_instruction(instr)?;
+            }
+        }
+        // create function
+        else if opcode == ':' {
+            let mut name = String::new();
+            loop {
+                let next_byte = self.read_byte("function name")?;
+                if next_byte == ':' {
+                    break;
                 } else {
-                    panic!("Unknown function callee");
+                    name.push(next_byte);
                 }
-            } else {
-                match opcode {
-                    '+' => {
-                        self.add_instruction(Instruction::new(address, Mnemonic::ADD, Vec::new()))
-                    }
-                    '-' => {
-                        self.add_instruction(Instruction::new(address, Mnemonic::SUB, Vec::new()))
-                    }
-                    '*' => {
-                        self.add_instruction(Instruction::new(address, Mnemonic::MUL, Vec::new()))
-                    }
-                    '/' => {
-                        self.add_instruction(Instruction::new(address, Mnemonic::DIV, Vec::new()))
-                    }
-                    'm' => {
-                        self.add_instruction(Instruction::new(address, Mnemonic::MIN, Vec::new()))
-                    }
-                    'M' => {
-                        self.add_instruction(Instruction::new(address, Mnemonic::MAX, Vec::new()))
-                    }
-                    '0' => {
-                        let literal = Operand::Literal(Literal::ZERO);
-                        self.add_instruction(Instruction::new(
-                            address,
-                            Mnemonic::PUSH,
-                            vec![literal],
-                        ))
-                    }
-                    '1' => {
-                        let literal = Operand::Literal(Literal::INFINITY);
-                        self.add_instruction(Instruction::new(
-                            address,
-                            Mnemonic::PUSH,
-                            vec![literal],
-                        ))
-                    }
-                    '\'' => {
-                        self.add_instruction(Instruction::new(address, Mnemonic::FRAC, Vec::new()))
-                    }
-                    '!' => {
-                        self.add_instruction(Instruction::new(address, Mnemonic::NEG, Vec::new()))
+            }
+
+            let func = Function::new(address, name);
+            self.functions.push(func);
+            self.current_function_index = Some(self.num_functions);
+            self.num_functions += 1;
+        }
+        // call function
+        else if opcode == '^' {
+            let next_bytecode: String = self.bytecode[self.pos..].iter().collect();
+
+            // like the VM, the callee is the first function in definition
+            // order whose name is a prefix of the following bytes, even if a
+            // later function matches more of them
+            let matches: Vec<&String> = self
+                .names
+                .iter()
+                .filter(|name| next_bytecode.starts_with(name.as_str()))
+                .collect();
+            let Some(name) = matches.first().map(|name| name.to_string()) else {
+                return Err(DisassemblyError::UnknownCallee {
+                    offset: address,
+                    lookahead: next_bytecode.chars().take(10).collect(),
+                });
+            };
+            if matches.len() > 1 {
+                self.warnings.push(DisassemblyWarning::AmbiguousCallee {
+                    offset: address,
+                    callee: name.clone(),
+                    candidates: matches
+                        .iter()
+                        .skip(1)
+                        .map(|name| name.to_string())
+                        .collect(),
+                });
+            }
+
+            // the callee may not have been disassembled yet
+            let func_ref = match self.functions.iter().find(|func| func.id() == name) {
+                Some(func) => func.get_reference(),
+                None => FunctionReference::new(name.clone()),
+            };
+            let operands = vec![Operand::FunctionReference(func_ref)];
+            let mut instr = Instruction::new(address, Mnemonic::CALL, operands);
+            // the VM skips past the name of the callee
+            let length = name.chars().count();
+            instr.set_length(1 + length);
+            self.add_instruction(instr)?;
+            self.pos += length;
+        } else {
+            let instr = match opcode {
+                '+' => Instruction::new(address, Mnemonic::ADD, Vec::new()),
+                '-' => Instruction::new(address, Mnemonic::SUB, Vec::new()),
+                '*' => Instruction::new(address, Mnemonic::MUL, Vec::new()),
+                '/' => Instruction::new(address, Mnemonic::DIV, Vec::new()),
+                'm' => Instruction::new(address, Mnemonic::MIN, Vec::new()),
+                'M' => Instruction::new(address, Mnemonic::MAX, Vec::new()),
+                '0' => {
+                    let literal = Operand::Literal(Literal::ZERO);
+                    Instruction::new(address, Mnemonic::PUSH, vec![literal])
+                }
+                '1' => {
+                    let literal = Operand::Literal(Literal::INFINITY);
+                    Instruction::new(address, Mnemonic::PUSH, vec![literal])
+                }
+                '\'' => Instruction::new(address, Mnemonic::FRAC, Vec::new()),
+                '!' => Instruction::new(address, Mnemonic::NEG, Vec::new()),
+                '.' => Instruction::new(address, Mnemonic::POP, Vec::new()),
+                // the VM skips any other byte, which is only worth
+                // reporting when recovering from corrupt bytecode
+                _ => {
+                    if self.recover && !opcode.is_whitespace() {
+                        self.diagnostics.push(DisassemblyError::UnknownOpcode {
+                            offset: address,
+                            opcode,
+                        });
                     }
-                    '.' => {
-                        self.add_instruction(Instruction::new(address, Mnemonic::POP, Vec::new()))
+                    // kept so that the bytecode can be encoded again
+                    if self.stores.is_empty() && self.current_function_index.is_none() {
+                        self.add_trivia(&opcode.to_string());
+                        return Ok(());
                     }
-                    _ => {}
+                    let operands = vec![Operand::RawBytes(RawBytes::new(opcode.to_string()))];
+                    Instruction::new(address, Mnemonic::UNKNOWN, operands)
                 }
-            }
+            };
+            self.add_instruction(instr)?;
         }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Disassembler;
+    use crate::{
+        assembler::assembler::Assembler,
+        disassembler::{error::DisassemblyWarning, operand::Operand},
+    };
+
+    #[test]
+    fn encode_reproduces_input() {
+        let bytecode = fs::read_to_string("input/bytecode.txt").unwrap();
+        let algo = fs::read_to_string("input/algo.txt").unwrap();
+
+        let mut disassembler = Disassembler::new(bytecode.clone());
+        disassembler.disassemble().unwrap();
+        disassembler
+            .disassemble_snippet(String::from("algorithm"), algo.clone())
+            .unwrap();
+
+        assert_eq!(disassembler.encode().unwrap(), vec![bytecode, algo]);
+    }
+
+    #[test]
+    fn display_names_are_comments() {
+        let bytecode = String::from(":a:23m;:b:..23^a;");
+        let mut disassembler = Disassembler::new(bytecode.clone());
+        disassembler.disassemble().unwrap();
+        disassembler.set_display_name("a", String::from("and"));
+
+        let listing = disassembler.get_disassembly();
+        assert!(listing[0].starts_with("function func_a("));
+        assert!(listing[0].ends_with("\t; and"));
+        assert!(listing
+            .iter()
+            .any(|line| line.contains("call") && line.ends_with(" func_a\t; and")));
+
+        let mut assembler = Assembler::new(listing.join("\n"));
+        assembler.assemble().unwrap();
+        assert_eq!(assembler.get_bytecode(&[]), bytecode);
+    }
+
+    #[test]
+    fn recovery_skips_to_next_function() {
+        let bytecode = String::from(":f:2^zz3;:g:^zz:h:3;");
+        let mut disassembler = Disassembler::new(bytecode.clone());
+        disassembler.set_recover(true);
+        disassembler.disassemble().unwrap();
+        assert_eq!(disassembler.diagnostics().len(), 2);
+
+        // the invalid bytes end at the delimiter, which still returns
+        let functions = disassembler.functions();
+        let ids: Vec<&str> = functions.iter().map(|func| func.id()).collect();
+        assert_eq!(ids, ["f", "g", "h"]);
+        let mnemonics = |index: usize| -> Vec<String> {
+            functions[index]
+                .instructions()
+                .iter()
+                .map(|instr| instr.mnemonic().to_string())
+                .collect()
+        };
+        assert_eq!(mnemonics(0), ["push", "invalid", "ret"]);
+        assert_eq!(functions[0].instructions()[1].span(), 4..8);
+
+        // or at the next function, which is disassembled as usual
+        assert_eq!(mnemonics(1), ["invalid"]);
+        assert_eq!(functions[1].instructions()[0].span(), 12..15);
+        assert_eq!(mnemonics(2), ["push", "ret"]);
+
+        assert_eq!(disassembler.encode().unwrap(), vec![bytecode]);
+    }
+
+    /// Returns the callee and the instructions after the call in `t`.
+    fn resolve_call(bytecode: &str) -> (String, Vec<String>, Vec<DisassemblyWarning>) {
+        let mut disassembler = Disassembler::new(bytecode.to_string());
+        disassembler.disassemble().unwrap();
+        let function = disassembler
+            .functions()
+            .iter()
+            .find(|func| func.id() == "t")
+            .unwrap();
+        let callee = match function.instructions()[0].operands().first() {
+            Some(Operand::FunctionReference(func_ref)) => func_ref.id().to_string(),
+            _ => panic!("expected a call"),
+        };
+        let rest = function.instructions()[1..]
+            .iter()
+            .map(|instr| instr.to_string())
+            .collect();
+        (callee, rest, disassembler.warnings().clone())
+    }
+
+    #[test]
+    fn first_defined_prefix_is_called() {
+        // the shorter name is defined first, so the rest of the longer name
+        // is disassembled as instructions
+        let (callee, rest, warnings) = resolve_call(":t:^ab;:a:2;:ab:3;");
+        assert_eq!(callee, "a");
+        assert_eq!(rest.len(), 2);
+        assert!(rest[0].contains("push") && rest[0].ends_with("global_b"));
+        assert_eq!(
+            warnings,
+            vec![DisassemblyWarning::AmbiguousCallee {
+                offset: 3,
+                callee: String::from("a"),
+                candidates: vec![String::from("ab")],
+            }]
+        );
+
+        // the longer name is defined first, so it is called
+        let (callee, rest, warnings) = resolve_call(":t:^ab;:ab:3;:a:2;");
+        assert_eq!(callee, "ab");
+        assert_eq!(rest.len(), 1);
+        assert_eq!(warnings.len(), 1);
+
+        // a name which is only a prefix of one function is not ambiguous
+        let (callee, _, warnings) = resolve_call(":t:^ab;:ab:3;:b:2;");
+        assert_eq!(callee, "ab");
+        assert!(warnings.is_empty());
     }
 }
@@ -0,0 +1,195 @@
+use std::{error::Error, fmt::Display};
+
+/// An error found while disassembling bytecode. Offsets are addresses, i.e.
+/// they include the base address of the snippet being disassembled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisassemblyError {
+    /// The bytecode ended while reading something, such as a function name.
+    UnexpectedEnd { offset: usize, context: String },
+    /// A call whose callee does not match the name of any function.
+    UnknownCallee { offset: usize, lookahead: String },
+    /// An instruction outside of any function.
+    NoCurrentFunction { offset: usize, mnemonic: String },
+    /// A store whose block is not ended by a delimiter.
+    UnterminatedStore { offset: usize, variable: char },
+    /// A byte which is not an opcode. Only reported when recovering.
+    UnknownOpcode { offset: usize, opcode: char },
+}
+
+impl DisassemblyError {
+    /// Returns the offset the error was found at.
+    pub fn offset(&self) -> usize {
+        match self {
+            DisassemblyError::UnexpectedEnd { offset, .. }
+            | DisassemblyError::UnknownCallee { offset, .. }
+            | DisassemblyError::NoCurrentFunction { offset, .. }
+            | DisassemblyError::UnterminatedStore { offset, .. }
+            | DisassemblyError::UnknownOpcode { offset, .. } => *offset,
+        }
+    }
+}
+
+impl Display for DisassemblyError {
+    /// Formats the error as a message.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisassemblyError::UnexpectedEnd { offset, context } => {
+                write!(f, "0x{offset:x}: unexpected end of bytecode in {context}")
+            }
+            DisassemblyError::UnknownCallee { offset, lookahead } => {
+                write!(f, "0x{offset:x}: unknown callee at {lookahead:?}")
+            }
+            DisassemblyError::NoCurrentFunction { offset, mnemonic } => {
+                write!(f, "0x{offset:x}: {mnemonic} is outside of a function")
+            }
+            DisassemblyError::UnterminatedStore { offset, variable } => {
+                write!(f, "0x{offset:x}: store to {variable:?} is never terminated")
+            }
+            DisassemblyError::UnknownOpcode { offset, opcode } => {
+                write!(f, "0x{offset:x}: unknown opcode {opcode:?}")
+            }
+        }
+    }
+}
+
+impl Error for DisassemblyError {}
+
+/// A construct which disassembles, but probably not as intended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisassemblyWarning {
+    /// A call where the names of several functions are prefixes of the
+    /// following bytes. The VM calls the first function defined.
+    AmbiguousCallee {
+        offset: usize,
+        callee: String,
+        candidates: Vec<String>,
+    },
+}
+
+impl DisassemblyWarning {
+    /// Returns the offset the warning was found at.
+    pub fn offset(&self) -> usize {
+        match self {
+            DisassemblyWarning::AmbiguousCallee { offset, .. } => *offset,
+        }
+    }
+}
+
+impl Display for DisassemblyWarning {
+    /// Formats the warning as a message.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisassemblyWarning::AmbiguousCallee {
+                offset,
+                callee,
+                candidates,
+            } => write!(
+                f,
+                "0x{offset:x}: call resolves to func_{callee}, but also matches {}",
+                candidates
+                    .iter()
+                    .map(|name| format!("func_{name}"))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DisassemblyError;
+    use crate::disassembler::disassembler::Disassembler;
+
+    #[test]
+    fn truncated_store_is_reported_at_the_store() {
+        let mut disassembler = Disassembler::new(String::from(":f:2a=3+"));
+        let error = disassembler.disassemble().unwrap_err();
+        assert_eq!(
+            error,
+            DisassemblyError::UnterminatedStore {
+                offset: 4,
+                variable: 'a'
+            }
+        );
+        assert_eq!(error.to_string(), "0x4: store to 'a' is never terminated");
+
+        // offsets in a snippet follow on from the bytecode before it
+        let mut disassembler = Disassembler::new(String::from(":f:2;"));
+        disassembler.disassemble().unwrap();
+        let error = disassembler
+            .disassemble_snippet(String::from("algorithm"), String::from("3b=2"))
+            .unwrap_err();
+        assert_eq!(error.offset(), 6);
+
+        // when recovering, the same error is recorded
+        let mut disassembler = Disassembler::new(String::from(":f:2a=3+"));
+        disassembler.set_recover(true);
+        disassembler.disassemble().unwrap();
+        assert_eq!(disassembler.diagnostics().len(), 1);
+        assert_eq!(disassembler.diagnostics()[0].offset(), 4);
+    }
+
+    #[test]
+    fn unterminated_header_is_reported_at_the_end() {
+        let mut disassembler = Disassembler::new(String::from(":f:2;:gh"));
+        let error = disassembler.disassemble().unwrap_err();
+        assert_eq!(
+            error,
+            DisassemblyError::UnexpectedEnd {
+                offset: 8,
+                context: String::from("function name")
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "0x8: unexpected end of bytecode in function name"
+        );
+    }
+
+    #[test]
+    fn unknown_callee_is_reported_at_the_call() {
+        let mut disassembler = Disassembler::new(String::from(":f:2;:t:^zz;"));
+        let error = disassembler.disassemble().unwrap_err();
+        assert_eq!(
+            error,
+            DisassemblyError::UnknownCallee {
+                offset: 8,
+                lookahead: String::from("zz;")
+            }
+        );
+        assert_eq!(error.to_string(), "0x8: unknown callee at \"zz;\"");
+    }
+
+    #[test]
+    fn instruction_before_any_function_is_reported() {
+        let mut disassembler = Disassembler::new(String::from("2:f:3;"));
+        let error = disassembler.disassemble().unwrap_err();
+        assert_eq!(
+            error,
+            DisassemblyError::NoCurrentFunction {
+                offset: 0,
+                mnemonic: String::from("push")
+            }
+        );
+        assert_eq!(error.to_string(), "0x0: push is outside of a function");
+    }
+
+    #[test]
+    fn unknown_opcode_is_only_reported_when_recovering() {
+        let mut disassembler = Disassembler::new(String::from(":f:2#3;"));
+        disassembler.disassemble().unwrap();
+        assert!(disassembler.diagnostics().is_empty());
+
+        let mut disassembler = Disassembler::new(String::from(":f:2#3;"));
+        disassembler.set_recover(true);
+        disassembler.disassemble().unwrap();
+        let error = DisassemblyError::UnknownOpcode {
+            offset: 4,
+            opcode: '#',
+        };
+        assert_eq!(disassembler.diagnostics(), &vec![error.clone()]);
+        assert_eq!(error.offset(), 4);
+        assert_eq!(error.to_string(), "0x4: unknown opcode '#'");
+    }
+}
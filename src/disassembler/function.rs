@@ -1,43 +1,123 @@
+use std::{collections::HashMap, error::Error};
+
 use super::{
     instruction::Instruction,
-    operand::{FunctionReference, Variable},
+    operand::{FunctionReference, Operand, Variable},
+    stack_effect::{instruction_slots, StackEffect},
 };
 
+/// Number of arguments the VM copies from the stack when calling a function.
+pub const NUM_ARGUMENTS: usize = 8;
+
 #[derive(Debug)]
 pub struct Function {
     address: usize,
     id: String,
     formatted_name: String,
-    num_params: usize,
+    display_name: Option<String>,
+    stack_effect: Option<StackEffect>,
     instructions: Vec<Instruction>,
+    snippet: bool,
+    trailing: String,
 }
 
 impl Function {
     /// Creates a new function.
-    pub fn new(address: usize, id: String, num_params: usize) -> Function {
+    pub fn new(address: usize, id: String) -> Function {
         let formatted_name = format!("func_{id}");
         Function {
             address,
-            id: id,
+            id,
             formatted_name,
-            num_params,
+            display_name: None,
+            stack_effect: None,
             instructions: Vec::new(),
+            snippet: false,
+            trailing: String::new(),
         }
     }
 
+    /// Creates a new function for a snippet of bytecode, which is run on its
+    /// own rather than defined with a header.
+    pub fn new_snippet(address: usize, id: String) -> Function {
+        Function {
+            snippet: true,
+            ..Function::new(address, id)
+        }
+    }
+
+    /// Returns the address of the function.
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
     /// Returns the ID of the function.
     pub fn id(&self) -> &str {
         &self.id
     }
 
-    /// Returns the number of parameters the function has.
+    /// Returns whether the function is a snippet of bytecode rather than a
+    /// defined function.
+    pub fn is_snippet(&self) -> bool {
+        self.snippet
+    }
+
+    /// Returns the bytes after the function which are outside of any
+    /// function, such as a delimiter ending the bytecode.
+    pub fn trailing(&self) -> &str {
+        &self.trailing
+    }
+
+    /// Appends bytes which are outside of any function after the function.
+    pub fn add_trailing(&mut self, bytes: &str) {
+        self.trailing.push_str(bytes);
+    }
+
+    /// Encodes the function as bytecode, followed by the bytes after it which
+    /// are outside of any function.
+    pub fn encode(&self) -> Result<String, Box<dyn Error>> {
+        let mut bytes = if self.snippet {
+            String::new()
+        } else {
+            format!(":{}:", self.id)
+        };
+        for instr in &self.instructions {
+            bytes.push_str(&instr.encode()?);
+        }
+        bytes.push_str(&self.trailing);
+        Ok(bytes)
+    }
+
+    /// Returns the number of parameters the function has, i.e. the number of
+    /// leading arguments it can observe. Until the stack effect of the
+    /// function is inferred, every argument is assumed to be a parameter.
     pub fn num_params(&self) -> usize {
-        self.num_params
+        self.stack_effect
+            .map_or(NUM_ARGUMENTS, |effect| effect.arguments())
+    }
+
+    /// Returns the inferred stack effect of the function, if it has been
+    /// inferred.
+    pub fn stack_effect(&self) -> Option<&StackEffect> {
+        self.stack_effect.as_ref()
+    }
+
+    /// Sets the inferred stack effect of the function.
+    pub fn set_stack_effect(&mut self, effect: StackEffect) {
+        self.stack_effect = Some(effect);
     }
 
     /// Returns the instructions within the function.
-    pub fn instructions(self) -> Vec<Instruction> {
-        self.instructions
+    pub fn instructions(&self) -> &Vec<Instruction> {
+        &self.instructions
+    }
+
+    /// Returns every instruction within the function, including those in
+    /// nested blocks, in the order they appear in the bytecode.
+    pub fn all_instructions(&self) -> Vec<&Instruction> {
+        let mut instructions = Vec::new();
+        flatten(&self.instructions, &mut instructions);
+        instructions
     }
 
     /// Adds an instruction to the function.
@@ -45,34 +125,187 @@ impl Function {
         self.instructions.push(instr);
     }
 
+    /// Returns the descriptive name of the function, if it has one.
+    pub fn display_name(&self) -> Option<&str> {
+        self.display_name.as_deref()
+    }
+
+    /// Sets the descriptive name of the function, which is shown in a comment
+    /// on its header. The function is still referred to by its ID, so the
+    /// disassembly assembles to the same bytecode.
+    pub fn set_display_name(&mut self, name: String) {
+        self.display_name = Some(name);
+    }
+
+    /// Sets the descriptive name of the function with the given ID, for calls
+    /// to it within this function.
+    pub fn set_reference_display_names(&mut self, id: &str, name: &str) {
+        set_reference_display_names(&mut self.instructions, id, name);
+    }
+
     /// Returns a reference to the function. Can be used for referring
     /// to calls of the function within instructions.
     pub fn get_reference(&self) -> FunctionReference {
-        FunctionReference::new(self.id.clone())
+        let mut func_ref = FunctionReference::new(self.id.clone());
+        if let Some(name) = &self.display_name {
+            func_ref.set_display_name(name.clone());
+        }
+        func_ref
     }
 
     /// Returns the disassembly for the function as a vector of disassembly lines.
     pub fn get_disassembly(&self) -> Vec<String> {
-        let param_str = (0..self.num_params)
-            .map(|i| {
-                format!(
-                    "param_{i}{}",
-                    if i < self.num_params - 1 { ", " } else { "" }
-                )
-            })
+        let mut dis: Vec<String> = vec![self.get_header()];
+        disassemble_block(&self.instructions, 1, &mut dis);
+        // kept so that the listing assembles to the same bytecode
+        if !self.trailing.is_empty() {
+            dis.push(format!("\t{:<12}{:?}", "bytes", self.trailing));
+        }
+        dis
+    }
+
+    /// Returns the disassembly for the function, with each instruction
+    /// annotated with the stack depth before and after it and the stack slots
+    /// it reads. Depths and slots are relative to the top of the stack when
+    /// the function is called, so slot -1 holds param_0. Given the number of
+    /// slots on the stack when the function is called, reads below the
+    /// bottom of the stack are marked as underflows, which read garbage in
+    /// the VM.
+    pub fn get_annotated_disassembly(
+        &self,
+        stack_effects: &HashMap<String, StackEffect>,
+        entry_depth: usize,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut dis: Vec<String> = vec![self.get_header()];
+        let mut depth = 0;
+        annotate_block(
+            &self.instructions,
+            stack_effects,
+            -(entry_depth as isize),
+            1,
+            &mut depth,
+            &mut dis,
+        )?;
+        Ok(dis)
+    }
+
+    /// Returns the line declaring the function, with its descriptive name and
+    /// its stack effect if it has been inferred.
+    fn get_header(&self) -> String {
+        let param_str = (0..self.num_params())
+            .map(|i| format!("param_{i}"))
             .collect::<Vec<String>>()
-            .join("");
-        let func_line = format!("function {}({})", self.formatted_name, param_str);
+            .join(", ");
+        let mut func_line = format!("function {}({})", self.formatted_name, param_str);
+        let mut comments = Vec::new();
+        if let Some(name) = &self.display_name {
+            comments.push(name.clone());
+        }
+        if let Some(effect) = &self.stack_effect {
+            let read = match effect.read().as_slice() {
+                [] => String::from("nothing"),
+                read => read
+                    .iter()
+                    .map(|i| format!("param_{i}"))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+            };
+            comments.push(format!(
+                "consumes {}, produces {}, reads {read}",
+                effect.consumed(),
+                effect.produced()
+            ));
+        }
+        if !comments.is_empty() {
+            func_line.push_str(&format!("\t; {}", comments.join(", ")));
+        }
+        func_line
+    }
+}
 
-        let mut dis: Vec<String> = vec![func_line];
+/// Appends the annotated disassembly lines of a block, updating the stack
+/// depth. A store is annotated with the depth before its block runs and
+/// after its result is popped.
+fn annotate_block(
+    block: &[Instruction],
+    stack_effects: &HashMap<String, StackEffect>,
+    bottom: isize,
+    indent: usize,
+    depth: &mut isize,
+    dis: &mut Vec<String>,
+) -> Result<(), Box<dyn Error>> {
+    for instr in block {
+        let before = *depth;
+        let line_index = dis.len();
+        dis.push(String::new());
+        annotate_block(instr.block(), stack_effects, bottom, indent + 1, depth, dis)?;
 
-        for instr in &self.instructions {
-            let mut line = String::from("\t");
-            line.push_str(&instr.to_string());
-            dis.push(line);
+        let (reads, pops, pushes) = instruction_slots(instr, stack_effects)?;
+        let slots: Vec<isize> = (1..=reads as isize).map(|i| *depth - i).collect();
+        *depth += pushes as isize - pops as isize;
+
+        let mut line = "\t".repeat(indent);
+        line.push_str(&instr.to_string());
+        let mut annotation = match callee_display_name(instr) {
+            Some(name) => format!("{name}, {before} -> {depth}"),
+            None => format!("{before} -> {depth}"),
+        };
+        if !slots.is_empty() {
+            let slots: Vec<String> = slots.iter().map(|slot| slot.to_string()).collect();
+            annotation.push_str(&format!(", reads {}", slots.join(", ")));
         }
+        if slots.iter().any(|slot| *slot < bottom) {
+            annotation.push_str(", underflow");
+        }
+        dis[line_index] = format!("{line}\t; {annotation}");
+    }
+    Ok(())
+}
 
-        dis
+/// Appends the instructions of a block to a vector, followed by those of
+/// their nested blocks.
+fn flatten<'a>(block: &'a [Instruction], instructions: &mut Vec<&'a Instruction>) {
+    for instr in block {
+        instructions.push(instr);
+        flatten(instr.block(), instructions);
+    }
+}
+
+/// Sets the descriptive name of references to a function within a block and
+/// its nested blocks.
+fn set_reference_display_names(block: &mut [Instruction], id: &str, name: &str) {
+    for instr in block {
+        for operand in instr.operands_mut() {
+            if let Operand::FunctionReference(func_ref) = operand {
+                if func_ref.id() == id {
+                    func_ref.set_display_name(name.to_string());
+                }
+            }
+        }
+        set_reference_display_names(instr.block_mut(), id, name);
+    }
+}
+
+/// Returns the descriptive name of the function an instruction calls, if it
+/// has one.
+fn callee_display_name(instr: &Instruction) -> Option<&str> {
+    instr.operands().iter().find_map(|operand| match operand {
+        Operand::FunctionReference(func_ref) => func_ref.display_name(),
+        _ => None,
+    })
+}
+
+/// Appends the disassembly lines of a block, indenting nested blocks by an
+/// extra tab under the instruction which runs them.
+fn disassemble_block(block: &[Instruction], indent: usize, dis: &mut Vec<String>) {
+    for instr in block {
+        let mut line = "\t".repeat(indent);
+        line.push_str(&instr.to_string());
+        if let Some(name) = callee_display_name(instr) {
+            line.push_str(&format!("\t; {name}"));
+        }
+        dis.push(line);
+        disassemble_block(instr.block(), indent + 1, dis);
     }
 }
 
@@ -82,3 +315,76 @@ impl Variable for Function {
         &self.formatted_name
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        disassembler::disassembler::Disassembler,
+        emulator::emulator::{Emulator, NUM_ARGS},
+    };
+
+    /// Returns the annotations of the disassembly of function `f`, called
+    /// with the given number of slots on the stack.
+    fn annotations(bytecode: &str, entry_depth: usize) -> Vec<String> {
+        let mut disassembler = Disassembler::new(bytecode.to_string());
+        disassembler.disassemble().unwrap();
+        let stack_effects = disassembler.infer_stack_effects().unwrap();
+        let function = disassembler
+            .functions()
+            .iter()
+            .find(|func| func.id() == "f")
+            .unwrap();
+        function
+            .get_annotated_disassembly(&stack_effects, entry_depth)
+            .unwrap()
+            .iter()
+            .skip(1)
+            .map(|line| line.split_once("\t; ").unwrap().1.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn annotations_track_depth() {
+        // a store is annotated after its block, with the depth before it
+        let annotations = annotations(":f:.2a=3;+;", 2);
+        assert_eq!(
+            annotations,
+            [
+                "0 -> -1, reads -1",
+                "-1 -> 0",
+                "0 -> 0, reads 0",
+                "0 -> 1",
+                "0 -> -1, reads -1, -2",
+                "-1 -> -1",
+            ]
+        );
+    }
+
+    #[test]
+    fn top_slot_holds_param_0() {
+        // `f` pops slot -1 and pushes param_0 in its place, which leaves the
+        // stack unchanged only if they are the same value
+        let bytecode = ":f:.2;:t:01^f;";
+        assert_eq!(annotations(bytecode, 2)[0], "0 -> -1, reads -1");
+
+        let mut disassembler = Disassembler::new(bytecode.to_string());
+        disassembler.disassemble().unwrap();
+        let mut emulator = Emulator::new(disassembler.functions());
+        emulator.run("t", &[0.0; NUM_ARGS]).unwrap();
+        assert_eq!(emulator.stack(), &vec![0.0, f32::INFINITY]);
+    }
+
+    #[test]
+    fn reads_below_entry_are_underflows() {
+        let annotations = annotations(":f:.2a=3;+;", 1);
+        assert_eq!(annotations[0], "0 -> -1, reads -1");
+        assert_eq!(annotations[4], "0 -> -1, reads -1, -2, underflow");
+        assert_eq!(
+            annotations
+                .iter()
+                .filter(|a| a.ends_with("underflow"))
+                .count(),
+            1
+        );
+    }
+}
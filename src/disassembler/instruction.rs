@@ -1,6 +1,6 @@
-use std::fmt::Display;
+use std::{error::Error, fmt::Display, ops::Range};
 
-use super::operand::Operand;
+use super::operand::{Literal, Operand};
 
 #[derive(Debug)]
 pub enum Mnemonic {
@@ -16,6 +16,12 @@ pub enum Mnemonic {
     MAX,
     FRAC,
     NEG,
+    STORE,
+    /// A byte which is not an opcode, which the VM skips.
+    UNKNOWN,
+    /// Bytes which could not be disassembled, up to the next function or
+    /// delimiter.
+    INVALID,
 }
 
 impl Display for Mnemonic {
@@ -34,6 +40,9 @@ impl Display for Mnemonic {
             Mnemonic::MAX => "max",
             Mnemonic::FRAC => "frac",
             Mnemonic::NEG => "neg",
+            Mnemonic::STORE => "store",
+            Mnemonic::UNKNOWN => "unknown",
+            Mnemonic::INVALID => "invalid",
         };
         write!(f, "{}", repr)
     }
@@ -44,6 +53,8 @@ pub struct Instruction {
     address: usize,
     mnemonic: Mnemonic,
     operands: Vec<Operand>,
+    block: Vec<Instruction>,
+    length: usize,
 }
 
 impl Instruction {
@@ -53,6 +64,8 @@ impl Instruction {
             address,
             mnemonic,
             operands,
+            block: Vec::new(),
+            length: 1,
         }
     }
 
@@ -61,6 +74,18 @@ impl Instruction {
         &self.address
     }
 
+    /// Returns the addresses of the bytes the instruction was disassembled
+    /// from, including those of its nested block.
+    pub fn span(&self) -> Range<usize> {
+        self.address..self.address + self.length
+    }
+
+    /// Sets the number of bytes the instruction was disassembled from. Most
+    /// instructions are a single byte.
+    pub fn set_length(&mut self, length: usize) {
+        self.length = length;
+    }
+
     /// Returns the mnemonic of the instruction.
     pub fn mnemonic(&self) -> &Mnemonic {
         &self.mnemonic
@@ -71,15 +96,76 @@ impl Instruction {
         &self.operands
     }
 
-    /// Returns a string representation of the instruction.
-    pub fn to_string(&self) -> String {
+    /// Returns the operands of the instruction mutably.
+    pub fn operands_mut(&mut self) -> &mut Vec<Operand> {
+        &mut self.operands
+    }
+
+    /// Returns the nested block of the instruction. A store runs its block
+    /// and stores the value it leaves on top of the stack.
+    pub fn block(&self) -> &Vec<Instruction> {
+        &self.block
+    }
+
+    /// Returns the nested block of the instruction mutably.
+    pub fn block_mut(&mut self) -> &mut Vec<Instruction> {
+        &mut self.block
+    }
+
+    /// Encodes the instruction, along with its nested block, as bytecode.
+    pub fn encode(&self) -> Result<String, Box<dyn Error>> {
+        let bytes = match (&self.mnemonic, self.operands.first()) {
+            (Mnemonic::PUSH, Some(Operand::Literal(Literal::ZERO))) => String::from("0"),
+            (Mnemonic::PUSH, Some(Operand::Literal(Literal::INFINITY))) => String::from("1"),
+            (Mnemonic::PUSH, Some(Operand::GlobalVariable(var))) => var.id().to_string(),
+            // parameters are pushed by the digits from 2
+            (Mnemonic::PUSH, Some(Operand::Parameter(param))) => {
+                char::from_u32(param.index() as u32 + 50)
+                    .ok_or(format!("Cannot encode {}", param.to_string()))?
+                    .to_string()
+            }
+            (Mnemonic::POP, _) => String::from("."),
+            (Mnemonic::RET, _) => String::from(";"),
+            // the VM skips past exactly the name of the callee
+            (Mnemonic::CALL, Some(Operand::FunctionReference(func_ref))) => {
+                format!("^{}", func_ref.id())
+            }
+            (Mnemonic::ADD, _) => String::from("+"),
+            (Mnemonic::SUB, _) => String::from("-"),
+            (Mnemonic::MUL, _) => String::from("*"),
+            (Mnemonic::DIV, _) => String::from("/"),
+            (Mnemonic::MIN, _) => String::from("m"),
+            (Mnemonic::MAX, _) => String::from("M"),
+            (Mnemonic::FRAC, _) => String::from("'"),
+            (Mnemonic::NEG, _) => String::from("!"),
+            (Mnemonic::STORE, Some(Operand::GlobalVariable(var))) => {
+                let mut bytes = format!("{}=", var.id());
+                for instr in &self.block {
+                    bytes.push_str(&instr.encode()?);
+                }
+                bytes.push(';');
+                bytes
+            }
+            (Mnemonic::UNKNOWN | Mnemonic::INVALID, Some(Operand::RawBytes(raw))) => {
+                raw.bytes().to_string()
+            }
+            _ => return Err(format!("Cannot encode {self}, bad disassembly").into()),
+        };
+        Ok(bytes)
+    }
+}
+
+impl Display for Instruction {
+    /// Formats the instruction as a string.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let operand_str = self
             .operands
             .iter()
             .map(|op| op.to_string())
             .collect::<Vec<String>>()
             .join(", ");
-        format!(
+        write!(
+            f,
             "0x{:x}:\t{:<12}{}",
             self.address,
             self.mnemonic.to_string(),
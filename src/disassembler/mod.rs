@@ -1,4 +1,7 @@
+#[allow(clippy::module_inception)]
 pub mod disassembler;
+pub mod error;
 pub mod function;
 pub mod instruction;
 pub mod operand;
+pub mod stack_effect;
@@ -1,21 +1,25 @@
+use std::fmt::Display;
+
 #[derive(Debug)]
 pub enum Operand {
     Literal(Literal),
     GlobalVariable(GlobalVariable),
     Parameter(Parameter),
     FunctionReference(FunctionReference),
+    RawBytes(RawBytes),
 }
 
-impl Operand {
-    /// Returns a string representation of the operand.
-    pub fn to_string(&self) -> String {
+impl Display for Operand {
+    /// Formats the operand as a string.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let str = match self {
             Operand::Literal(literal) => literal.to_string(),
             Operand::GlobalVariable(var) => var.to_string(),
             Operand::Parameter(param) => param.to_string(),
             Operand::FunctionReference(func_ref) => func_ref.to_string(),
+            Operand::RawBytes(raw) => raw.to_string(),
         };
-        String::from(str)
+        write!(f, "{}", str)
     }
 }
 
@@ -42,18 +46,20 @@ pub trait Variable {
 
 #[derive(Debug)]
 pub struct GlobalVariable {
-    name: String,
+    id: String,
     formatted_name: String,
 }
 
 impl GlobalVariable {
     /// Creates a new global variable.
-    pub fn new(name: String) -> GlobalVariable {
-        let formatted_name = format!("global_{name}");
-        GlobalVariable {
-            name,
-            formatted_name,
-        }
+    pub fn new(id: String) -> GlobalVariable {
+        let formatted_name = format!("global_{id}");
+        GlobalVariable { id, formatted_name }
+    }
+
+    /// Returns the ID of the global variable, i.e. the opcode used to access it.
+    pub fn id(&self) -> &str {
+        &self.id
     }
 
     /// Returns a string representation of the global variable.
@@ -85,6 +91,11 @@ impl Parameter {
         }
     }
 
+    /// Returns the index of the parameter.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
     /// Returns a string representation of the parameter.
     pub fn to_string(&self) -> &str {
         &self.formatted_name
@@ -102,13 +113,35 @@ impl Variable for Parameter {
 pub struct FunctionReference {
     id: String,
     formatted_name: String,
+    display_name: Option<String>,
 }
 
 impl FunctionReference {
     /// Creates a new function reference.
     pub fn new(id: String) -> FunctionReference {
         let formatted_name = format!("func_{id}");
-        FunctionReference { id, formatted_name }
+        FunctionReference {
+            id,
+            formatted_name,
+            display_name: None,
+        }
+    }
+
+    /// Returns the ID of the referenced function.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the descriptive name of the referenced function, if it has
+    /// one.
+    pub fn display_name(&self) -> Option<&str> {
+        self.display_name.as_deref()
+    }
+
+    /// Sets the descriptive name of the referenced function, which is shown
+    /// in a comment on the call.
+    pub fn set_display_name(&mut self, name: String) {
+        self.display_name = Some(name);
     }
 
     /// Returns a string representation of the function reference.
@@ -123,3 +156,30 @@ impl Variable for FunctionReference {
         &self.formatted_name
     }
 }
+
+#[derive(Debug)]
+pub struct RawBytes {
+    bytes: String,
+    formatted_bytes: String,
+}
+
+impl RawBytes {
+    /// Creates a new operand for bytes which could not be disassembled.
+    pub fn new(bytes: String) -> RawBytes {
+        let formatted_bytes = format!("{bytes:?}");
+        RawBytes {
+            bytes,
+            formatted_bytes,
+        }
+    }
+
+    /// Returns the bytes.
+    pub fn bytes(&self) -> &str {
+        &self.bytes
+    }
+
+    /// Returns a string representation of the bytes, quoted and escaped.
+    pub fn to_string(&self) -> &str {
+        &self.formatted_bytes
+    }
+}
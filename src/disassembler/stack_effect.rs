@@ -0,0 +1,281 @@
+use std::{collections::HashMap, error::Error};
+
+use super::{
+    function::{Function, NUM_ARGUMENTS},
+    instruction::{Instruction, Mnemonic},
+    operand::Operand,
+};
+
+/// The effect a function has on the shared stack when it is called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackEffect {
+    consumed: usize,
+    produced: usize,
+    arguments: usize,
+    read: u8,
+}
+
+impl StackEffect {
+    /// Creates a new stack effect. Bit `i` of `read` is set if the function
+    /// pushes `param_i`.
+    pub fn new(consumed: usize, produced: usize, arguments: usize, read: u8) -> StackEffect {
+        StackEffect {
+            consumed,
+            produced,
+            arguments,
+            read,
+        }
+    }
+
+    /// Returns the number of caller stack slots the function pops.
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// Returns the number of slots the function leaves on top of the
+    /// caller's remaining stack.
+    pub fn produced(&self) -> usize {
+        self.produced
+    }
+
+    /// Returns the number of leading arguments the function can observe,
+    /// either by pushing them or by popping the stack slots they were
+    /// copied from.
+    pub fn arguments(&self) -> usize {
+        self.arguments
+    }
+
+    /// Returns the indices of the arguments the function pushes, as opposed
+    /// to only popping the slots they were copied from.
+    pub fn read(&self) -> Vec<usize> {
+        (0..NUM_ARGUMENTS)
+            .filter(|i| (self.read >> i) & 1 == 1)
+            .collect()
+    }
+
+    /// Returns the net change in stack size.
+    pub fn delta(&self) -> isize {
+        self.produced as isize - self.consumed as isize
+    }
+}
+
+/// Computes the stack effect of every function, keyed by function ID.
+pub fn compute_stack_effects(
+    functions: &[Function],
+) -> Result<HashMap<String, StackEffect>, Box<dyn Error>> {
+    let functions_by_id: HashMap<&str, &Function> =
+        functions.iter().map(|func| (func.id(), func)).collect();
+
+    let mut effects = HashMap::new();
+    for func in functions {
+        compute_stack_effect(func, &functions_by_id, &mut effects, &mut Vec::new())?;
+    }
+    Ok(effects)
+}
+
+/// Computes the stack effect of a function, first computing the effects of
+/// any functions it calls.
+fn compute_stack_effect(
+    function: &Function,
+    functions_by_id: &HashMap<&str, &Function>,
+    effects: &mut HashMap<String, StackEffect>,
+    call_chain: &mut Vec<String>,
+) -> Result<StackEffect, Box<dyn Error>> {
+    if let Some(effect) = effects.get(function.id()) {
+        return Ok(*effect);
+    }
+    if call_chain.iter().any(|id| id == function.id()) {
+        return Err(format!(
+            "Recursive call to func_{} has no stack effect",
+            function.id()
+        )
+        .into());
+    }
+    call_chain.push(function.id().to_string());
+
+    let mut walk = Walk::default();
+    walk_block(
+        function.instructions(),
+        functions_by_id,
+        effects,
+        call_chain,
+        &mut walk,
+    )?;
+
+    call_chain.pop();
+
+    let consumed = (-walk.min_depth) as usize;
+    let arguments = walk.arguments.max(consumed.min(NUM_ARGUMENTS));
+    let produced = (walk.depth - walk.min_depth) as usize;
+    let effect = StackEffect::new(consumed, produced, arguments, walk.read);
+    effects.insert(function.id().to_string(), effect);
+    Ok(effect)
+}
+
+/// Running totals while walking the instructions of a function.
+#[derive(Debug, Default)]
+struct Walk {
+    depth: isize,
+    min_depth: isize,
+    arguments: usize,
+    read: u8,
+}
+
+/// Walks a block of instructions, including nested blocks, updating the
+/// stack depth. Returns whether the function returned within the block.
+fn walk_block(
+    block: &[Instruction],
+    functions_by_id: &HashMap<&str, &Function>,
+    effects: &mut HashMap<String, StackEffect>,
+    call_chain: &mut Vec<String>,
+    walk: &mut Walk,
+) -> Result<bool, Box<dyn Error>> {
+    for instr in block {
+        if let Some(Operand::Parameter(param)) = instr.operands().first() {
+            walk.arguments = walk.arguments.max(param.index() + 1);
+            if param.index() < NUM_ARGUMENTS {
+                walk.read |= 1 << param.index();
+            }
+        }
+
+        let (pops, pushes) = match instr.mnemonic() {
+            Mnemonic::RET => return Ok(true),
+            Mnemonic::CALL => {
+                let callee = get_callee(instr, functions_by_id)?;
+                let effect = compute_stack_effect(callee, functions_by_id, effects, call_chain)?;
+                (effect.consumed(), effect.produced())
+            }
+            // the block runs first, then its result is popped into the variable
+            Mnemonic::STORE => {
+                if walk_block(instr.block(), functions_by_id, effects, call_chain, walk)? {
+                    return Ok(true);
+                }
+                instruction_stack_effect(instr.mnemonic())
+            }
+            mnemonic => instruction_stack_effect(mnemonic),
+        };
+        walk.depth -= pops as isize;
+        walk.min_depth = walk.min_depth.min(walk.depth);
+        walk.depth += pushes as isize;
+    }
+    Ok(false)
+}
+
+/// Returns the number of slots read, popped and pushed by an instruction,
+/// not counting the nested block of a store. A call reads every slot copied
+/// into an argument its callee can observe, as well as those it pops.
+pub fn instruction_slots(
+    instr: &Instruction,
+    stack_effects: &HashMap<String, StackEffect>,
+) -> Result<(usize, usize, usize), Box<dyn Error>> {
+    match (instr.mnemonic(), instr.operands().first()) {
+        (Mnemonic::CALL, Some(Operand::FunctionReference(func_ref))) => {
+            let effect = stack_effects
+                .get(func_ref.id())
+                .ok_or(format!("Unknown stack effect for {}", func_ref.to_string()))?;
+            Ok((
+                effect.consumed().max(effect.arguments()),
+                effect.consumed(),
+                effect.produced(),
+            ))
+        }
+        (Mnemonic::CALL, _) => Err("Expected function reference for call, bad disassembly".into()),
+        (mnemonic, _) => {
+            let (pops, pushes) = instruction_stack_effect(mnemonic);
+            Ok((pops, pops, pushes))
+        }
+    }
+}
+
+/// Returns the number of slots popped and pushed by an instruction with the
+/// given mnemonic. Calls and returns are handled separately, as is the
+/// nested block of a store.
+fn instruction_stack_effect(mnemonic: &Mnemonic) -> (usize, usize) {
+    match mnemonic {
+        Mnemonic::PUSH => (0, 1),
+        Mnemonic::POP => (1, 0),
+        Mnemonic::ADD
+        | Mnemonic::SUB
+        | Mnemonic::MUL
+        | Mnemonic::DIV
+        | Mnemonic::MIN
+        | Mnemonic::MAX => (2, 1),
+        Mnemonic::FRAC | Mnemonic::NEG => (1, 1),
+        Mnemonic::STORE => (1, 0),
+        // invalid bytes are assumed to have no effect, they are reported as
+        // diagnostics when disassembling
+        Mnemonic::UNKNOWN | Mnemonic::INVALID => (0, 0),
+        Mnemonic::CALL | Mnemonic::RET => (0, 0),
+    }
+}
+
+/// Looks up the function called by a call instruction.
+fn get_callee<'a>(
+    instr: &Instruction,
+    functions_by_id: &HashMap<&str, &'a Function>,
+) -> Result<&'a Function, Box<dyn Error>> {
+    match instr.operands().first() {
+        Some(Operand::FunctionReference(func_ref)) => functions_by_id
+            .get(func_ref.id())
+            .copied()
+            .ok_or(format!("Unknown callee {}", func_ref.to_string()).into()),
+        _ => Err("Expected function reference for call, bad disassembly".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{compute_stack_effects, StackEffect};
+    use crate::disassembler::disassembler::Disassembler;
+
+    /// Disassembles bytecode and computes the stack effects of its functions.
+    fn effects(bytecode: &str) -> Result<Vec<(String, StackEffect)>, String> {
+        let mut disassembler = Disassembler::new(bytecode.to_string());
+        disassembler.disassemble().unwrap();
+        let effects = compute_stack_effects(disassembler.functions()).map_err(|e| e.to_string())?;
+        Ok(disassembler
+            .functions()
+            .iter()
+            .map(|func| (func.id().to_string(), effects[func.id()]))
+            .collect())
+    }
+
+    #[test]
+    fn shipped_functions_have_expected_effects() {
+        let bytecode = fs::read_to_string("input/bytecode.txt").unwrap();
+        let effects = effects(&bytecode).unwrap();
+        let effect = |id: &str| effects.iter().find(|(func, _)| func == id).unwrap().1;
+
+        // each tuple is consumed, produced, arguments and the pushed params
+        let expected: [(&str, usize, usize, usize, Vec<usize>); 3] = [
+            ("i", 3, 1, 3, vec![0, 1, 2]),
+            ("g1", 2, 1, 2, vec![0, 1]),
+            ("xb", 8, 4, 8, (0..8).collect()),
+        ];
+        for (id, consumed, produced, arguments, read) in expected {
+            let effect = effect(id);
+            assert_eq!(effect.consumed(), consumed, "func_{id}");
+            assert_eq!(effect.produced(), produced, "func_{id}");
+            assert_eq!(effect.arguments(), arguments, "func_{id}");
+            assert_eq!(effect.read(), read, "func_{id}");
+        }
+    }
+
+    #[test]
+    fn arguments_include_popped_slots() {
+        // `f` pops two slots without pushing either parameter
+        let effects = effects(":f:..4;").unwrap();
+        assert_eq!(effects[0].1, StackEffect::new(2, 1, 3, 0b100));
+    }
+
+    #[test]
+    fn recursion_is_rejected() {
+        let error = effects(":f:2^g;:g:^f;").unwrap_err();
+        assert!(error.contains("Recursive call to func_f"), "{error}");
+
+        let error = effects(":f:2^f;").unwrap_err();
+        assert!(error.contains("Recursive call to func_f"), "{error}");
+    }
+}
@@ -0,0 +1,198 @@
+/// The order in which the two pops of a binary opcode, such as
+/// `push(pop() - pop())`, are evaluated. C leaves this unspecified, so it
+/// depends on the compiler the VM was built with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandOrder {
+    /// The left operand is popped first, so the top of the stack is the
+    /// left operand.
+    LeftFirst,
+    /// The right operand is popped first, so the top of the stack is the
+    /// right operand.
+    RightFirst,
+}
+
+/// Behaviour of a particular build of the VM which the C source leaves to
+/// the compiler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VmDialect {
+    operand_order: OperandOrder,
+}
+
+impl VmDialect {
+    /// The dialect of the reference build of `beyond.c`, compiled by gcc,
+    /// which evaluates the left pop first.
+    pub const GCC: VmDialect = VmDialect {
+        operand_order: OperandOrder::LeftFirst,
+    };
+
+    /// Creates a new dialect.
+    pub fn new(operand_order: OperandOrder) -> VmDialect {
+        VmDialect { operand_order }
+    }
+
+    /// Returns the order the operands of binary opcodes are popped in.
+    pub fn operand_order(&self) -> OperandOrder {
+        self.operand_order
+    }
+
+    /// Returns the left and right operands of a binary opcode, given the
+    /// values it pops in the order they are popped.
+    pub fn operands<T>(&self, first: T, second: T) -> (T, T) {
+        match self.operand_order {
+            OperandOrder::LeftFirst => (first, second),
+            OperandOrder::RightFirst => (second, first),
+        }
+    }
+}
+
+impl Default for VmDialect {
+    fn default() -> Self {
+        VmDialect::GCC
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{OperandOrder, VmDialect};
+    use crate::{
+        disassembler::disassembler::Disassembler,
+        emulator::{
+            emulator::{Emulator, NUM_ARGS},
+            harness::{parse_ciphertext, Harness, CHAIN_START, CIPHERTEXT},
+        },
+        symbolic::{state::CallMode, state_manager::StateManager},
+    };
+
+    const RIGHT_FIRST: VmDialect = VmDialect {
+        operand_order: OperandOrder::RightFirst,
+    };
+
+    /// Disassembles the bytecode and the algorithm snippet.
+    fn disassemble() -> Disassembler {
+        let bytecode = fs::read_to_string("input/bytecode.txt").unwrap();
+        let algo = fs::read_to_string("input/algo.txt").unwrap();
+        let mut disassembler = Disassembler::new(bytecode);
+        disassembler.disassemble().unwrap();
+        disassembler
+            .disassemble_snippet(String::from("algorithm"), algo)
+            .unwrap();
+        disassembler
+    }
+
+    /// Runs function `t` with the given body on the emulator, returning the
+    /// bits of the value it leaves on top, so that -0.0 and 0.0 are told
+    /// apart.
+    fn emulate(body: &str, dialect: VmDialect) -> u32 {
+        let mut disassembler = Disassembler::new(format!(":t:{body};"));
+        disassembler.disassemble().unwrap();
+        let mut emulator = Emulator::new(disassembler.functions());
+        emulator.set_dialect(dialect);
+        emulator.run("t", &[0.0; NUM_ARGS]).unwrap();
+        emulator.pop().unwrap().to_bits()
+    }
+
+    /// Symbolically executes function `t` with the given body, returning the
+    /// symbol it leaves on top.
+    fn execute(body: &str, dialect: VmDialect) -> String {
+        let mut disassembler = Disassembler::new(format!(":t:{body};"));
+        disassembler.disassemble().unwrap();
+        let stack_effects = disassembler.infer_stack_effects().unwrap();
+        let mut manager = StateManager::new(
+            &disassembler.functions()[0],
+            disassembler.functions(),
+            &stack_effects,
+            CallMode::Opaque,
+        );
+        manager.set_dialect(dialect);
+        manager.explore().unwrap();
+        let states = manager.returned_states();
+        states[0].stack().elements().last().unwrap().to_string()
+    }
+
+    #[test]
+    fn default_dialect_is_gcc() {
+        assert_eq!(VmDialect::default(), VmDialect::GCC);
+        assert_eq!(VmDialect::GCC.operand_order(), OperandOrder::LeftFirst);
+        assert_eq!(VmDialect::new(OperandOrder::RightFirst), RIGHT_FIRST);
+    }
+
+    #[test]
+    fn emulator_pops_left_operand_first() {
+        // with the top of the stack on the left: Infinity - 0.0,
+        // Infinity / 0.0 and min(-0.0, 0.0), whose tie gives the right operand
+        for (body, left_first, right_first) in [
+            ("01-", f32::INFINITY, f32::NEG_INFINITY),
+            ("01/", f32::INFINITY, 0.0),
+            ("00!m", 0.0, -0.0),
+        ] {
+            assert_eq!(
+                emulate(body, VmDialect::default()),
+                left_first.to_bits(),
+                "{body}"
+            );
+            assert_eq!(emulate(body, RIGHT_FIRST), right_first.to_bits(), "{body}");
+        }
+    }
+
+    #[test]
+    fn symbolic_engine_swaps_operands() {
+        for (body, left_first, right_first) in [
+            ("23-", "param_1 - param_0", "param_0 - param_1"),
+            ("23/", "param_1 / param_0", "param_0 / param_1"),
+            ("23m", "min(param_1, param_0)", "min(param_0, param_1)"),
+        ] {
+            assert_eq!(execute(body, VmDialect::default()), left_first);
+            assert_eq!(execute(body, RIGHT_FIRST), right_first);
+        }
+    }
+
+    #[test]
+    fn symbolic_engine_pops_left_operand_first() {
+        let mut disassembler = disassemble();
+        let stack_effects = disassembler.infer_stack_effects().unwrap();
+        let function = disassembler
+            .functions()
+            .iter()
+            .find(|func| func.id() == "n01")
+            .unwrap();
+
+        let returned = |dialect| {
+            let mut manager = StateManager::new(
+                function,
+                disassembler.functions(),
+                &stack_effects,
+                CallMode::Opaque,
+            );
+            manager.set_dialect(dialect);
+            manager.explore().unwrap();
+            let states = manager.returned_states();
+            assert_eq!(states.len(), 1);
+            states[0].stack().get(0).unwrap().to_string()
+        };
+        assert_eq!(returned(VmDialect::GCC), "Infinity - param_0");
+        assert_eq!(returned(RIGHT_FIRST), "param_0 - Infinity");
+    }
+
+    #[test]
+    fn gcc_dialect_reproduces_ciphertext() {
+        let disassembler = disassemble();
+        let (bytes, checksums) = parse_ciphertext(CIPHERTEXT).unwrap();
+
+        let encrypt = |dialect| {
+            let mut harness = Harness::new(disassembler.functions(), String::from("algorithm"));
+            harness.set_dialect(dialect);
+            harness.encrypt_chunk(*b"ac", CHAIN_START).unwrap()
+        };
+        let chunk = encrypt(VmDialect::GCC);
+        assert_eq!(chunk.bytes(), [bytes[0], bytes[1]]);
+        assert_eq!(chunk.checksum(), checksums[0]);
+
+        let chunk = encrypt(RIGHT_FIRST);
+        assert_ne!(
+            (chunk.bytes(), chunk.checksum()),
+            ([bytes[0], bytes[1]], checksums[0])
+        );
+    }
+}
@@ -0,0 +1,393 @@
+use std::{collections::HashMap, error::Error};
+
+use crate::disassembler::{
+    function::Function,
+    instruction::{Instruction, Mnemonic},
+    operand::{Literal, Operand},
+};
+
+use super::dialect::VmDialect;
+
+/// Number of slots in the VM's global stack.
+pub const STACK_SIZE: usize = 65536;
+
+/// Number of global variables, indexed by the byte of their opcode.
+pub const NUM_VARIABLES: usize = 256;
+
+/// Number of arguments each function receives.
+pub const NUM_ARGS: usize = 8;
+
+/// Maximum number of nested calls. The VM recurses on the C stack, so deeper
+/// calls would crash it rather than return.
+const MAX_CALL_DEPTH: usize = 256;
+
+#[derive(Debug)]
+pub struct Emulator<'a> {
+    functions: HashMap<&'a str, &'a Function>,
+    stack: Vec<f32>,
+    variables: [f32; NUM_VARIABLES],
+    dialect: VmDialect,
+}
+
+impl<'a> Emulator<'a> {
+    /// Creates a new emulator which can run the given functions.
+    pub fn new(functions: &'a [Function]) -> Emulator<'a> {
+        Emulator {
+            functions: functions.iter().map(|func| (func.id(), func)).collect(),
+            stack: Vec::new(),
+            variables: [0.0; NUM_VARIABLES],
+            dialect: VmDialect::GCC,
+        }
+    }
+
+    /// Sets the dialect of the VM being emulated, by default that of the
+    /// reference build.
+    pub fn set_dialect(&mut self, dialect: VmDialect) {
+        self.dialect = dialect;
+    }
+
+    /// Returns the stack, with the top of the stack as the last element.
+    pub fn stack(&self) -> &Vec<f32> {
+        &self.stack
+    }
+
+    /// Pushes a value to the stack.
+    pub fn push(&mut self, value: f32) -> Result<(), Box<dyn Error>> {
+        if self.stack.len() >= STACK_SIZE {
+            return Err("Stack overflow".into());
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// Pops a value from the stack.
+    pub fn pop(&mut self) -> Result<f32, Box<dyn Error>> {
+        self.stack
+            .pop()
+            .ok_or("Stack underflow, the VM would read garbage here".into())
+    }
+
+    /// Pops the two operands of a binary opcode, returning the left and
+    /// right operands.
+    fn pop_operands(&mut self) -> Result<(f32, f32), Box<dyn Error>> {
+        let first = self.pop()?;
+        let second = self.pop()?;
+        Ok(self.dialect.operands(first, second))
+    }
+
+    /// Returns the value of a global variable.
+    pub fn variable(&self, name: char) -> f32 {
+        self.variables[name as usize % NUM_VARIABLES]
+    }
+
+    /// Sets the value of a global variable.
+    pub fn set_variable(&mut self, name: char, value: f32) {
+        self.variables[name as usize % NUM_VARIABLES] = value;
+    }
+
+    /// Clears the stack and all global variables.
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.variables = [0.0; NUM_VARIABLES];
+    }
+
+    /// Runs a function with the given arguments, in the same way the harness
+    /// runs the top level bytecode.
+    pub fn run(&mut self, id: &str, args: &[f32; NUM_ARGS]) -> Result<(), Box<dyn Error>> {
+        let function = self.get_function(id)?;
+        self.execute_function(function, args, 0)
+    }
+
+    /// Calls a function in the same way as the `^` opcode. The top 8 stack
+    /// slots are copied into the arguments without being popped.
+    pub fn call(&mut self, id: &str) -> Result<(), Box<dyn Error>> {
+        self.call_at_depth(id, 1)
+    }
+
+    /// Calls a function from within the given number of nested calls.
+    fn call_at_depth(&mut self, id: &str, depth: usize) -> Result<(), Box<dyn Error>> {
+        let function = self.get_function(id)?;
+
+        // slots below the bottom of the stack are uninitialised in the VM,
+        // these are left as zero
+        let mut args = [0.0; NUM_ARGS];
+        for (j, arg) in args.iter_mut().enumerate() {
+            if j < self.stack.len() {
+                *arg = self.stack[self.stack.len() - j - 1];
+            }
+        }
+
+        self.execute_function(function, &args, depth)
+    }
+
+    /// Looks up a function by its ID.
+    fn get_function(&self, id: &str) -> Result<&'a Function, Box<dyn Error>> {
+        self.functions
+            .get(id)
+            .copied()
+            .ok_or(format!("Unknown function {id}").into())
+    }
+
+    /// Executes the instructions of a function until it returns, given the
+    /// number of calls it is nested within.
+    fn execute_function(
+        &mut self,
+        function: &'a Function,
+        args: &[f32; NUM_ARGS],
+        depth: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        if depth > MAX_CALL_DEPTH {
+            return Err(format!("Exceeded max call depth of {MAX_CALL_DEPTH}").into());
+        }
+        for instruction in function.instructions() {
+            if self.execute(instruction, args, depth)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Concretely executes an instruction within the given number of nested
+    /// calls. Returns whether the current function has returned.
+    fn execute(
+        &mut self,
+        instruction: &'a Instruction,
+        args: &[f32; NUM_ARGS],
+        depth: usize,
+    ) -> Result<bool, Box<dyn Error>> {
+        match instruction.mnemonic() {
+            Mnemonic::PUSH => {
+                let value = match instruction.operands().first() {
+                    Some(Operand::Literal(Literal::ZERO)) => 0.0,
+                    Some(Operand::Literal(Literal::INFINITY)) => f32::INFINITY,
+                    Some(Operand::GlobalVariable(var)) => {
+                        let name = var.id().chars().next().ok_or("Empty global variable")?;
+                        self.variable(name)
+                    }
+                    Some(Operand::Parameter(param)) => *args
+                        .get(param.index())
+                        .ok_or(format!("Parameter index {} out of range", param.index()))?,
+                    _ => return Err("Expected value operand for push, bad disassembly".into()),
+                };
+                self.push(value)?;
+            }
+            Mnemonic::POP => {
+                self.pop()?;
+            }
+            Mnemonic::ADD => {
+                let (left, right) = self.pop_operands()?;
+                self.push(left + right)?;
+            }
+            Mnemonic::SUB => {
+                let (left, right) = self.pop_operands()?;
+                self.push(left - right)?;
+            }
+            Mnemonic::MUL => {
+                let (left, right) = self.pop_operands()?;
+                self.push(left * right)?;
+            }
+            Mnemonic::DIV => {
+                let (left, right) = self.pop_operands()?;
+                self.push(left / right)?;
+            }
+            Mnemonic::MIN => {
+                let (left, right) = self.pop_operands()?;
+                self.push(fmin(left, right))?;
+            }
+            Mnemonic::MAX => {
+                let (left, right) = self.pop_operands()?;
+                self.push(fmax(left, right))?;
+            }
+            Mnemonic::FRAC => {
+                let operand = self.pop()?;
+                self.push(1.0 / operand)?;
+            }
+            Mnemonic::NEG => {
+                let operand = self.pop()?;
+                self.push(-operand)?;
+            }
+            Mnemonic::CALL => match instruction.operands().first() {
+                Some(Operand::FunctionReference(func_ref)) => {
+                    self.call_at_depth(func_ref.id(), depth + 1)?
+                }
+                _ => return Err("Expected function reference for call, bad disassembly".into()),
+            },
+            Mnemonic::STORE => {
+                let name = match instruction.operands().first() {
+                    Some(Operand::GlobalVariable(var)) => {
+                        var.id().chars().next().ok_or("Empty global variable")?
+                    }
+                    _ => return Err("Expected global variable for store, bad disassembly".into()),
+                };
+                // the block runs with the same arguments, then its result is
+                // popped into the variable
+                for instruction in instruction.block() {
+                    if self.execute(instruction, args, depth)? {
+                        return Ok(true);
+                    }
+                }
+                let value = self.pop()?;
+                self.set_variable(name, value);
+            }
+            // the VM skips bytes which are not opcodes
+            Mnemonic::UNKNOWN => {}
+            Mnemonic::INVALID => return Err("Cannot execute invalid bytecode".into()),
+            Mnemonic::RET => return Ok(true),
+        }
+
+        Ok(false)
+    }
+}
+
+/// Equivalent of C's `fmin`. NaNs are ignored, and if the operands compare
+/// equal (e.g. 0.0 and -0.0) the second operand is returned, as `minss` does.
+pub fn fmin(x: f32, y: f32) -> f32 {
+    if x.is_nan() {
+        y
+    } else if y.is_nan() || x < y {
+        x
+    } else {
+        y
+    }
+}
+
+/// Equivalent of C's `fmax`. NaNs are ignored, and if the operands compare
+/// equal (e.g. 0.0 and -0.0) the second operand is returned, as `maxss` does.
+pub fn fmax(x: f32, y: f32) -> f32 {
+    if x.is_nan() {
+        y
+    } else if y.is_nan() || x > y {
+        x
+    } else {
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Emulator, NUM_ARGS};
+    use crate::disassembler::disassembler::Disassembler;
+
+    /// Disassembles bytecode defining a single function `t`, and the given
+    /// other functions.
+    fn disassemble(body: &str, others: &str) -> Disassembler {
+        let mut disassembler = Disassembler::new(format!("{others}:t:{body};"));
+        disassembler.disassemble().unwrap();
+        disassembler
+    }
+
+    /// Runs `t` on the given first two arguments, returning the stack.
+    fn run(body: &str, arg0: f32, arg1: f32) -> Vec<f32> {
+        let disassembler = disassemble(body, "");
+        let mut emulator = Emulator::new(disassembler.functions());
+        let mut args = [0.0; NUM_ARGS];
+        args[0] = arg0;
+        args[1] = arg1;
+        emulator.run("t", &args).unwrap();
+        emulator.stack().clone()
+    }
+
+    /// Returns the bits of a value, so that -0.0 and 0.0 are told apart.
+    fn bits(values: &[f32]) -> Vec<u32> {
+        values.iter().map(|value| value.to_bits()).collect()
+    }
+
+    #[test]
+    fn min_and_max_ties_match_vm() {
+        // results of `ins()` in beyond.c built with gcc
+        for (body, expected) in [("23m", 0.0), ("32m", -0.0), ("23M", 0.0), ("32M", -0.0)] {
+            assert_eq!(bits(&run(body, 0.0, -0.0)), bits(&[expected]), "{body}");
+        }
+        assert_eq!(run("01m", 0.0, 0.0), [0.0]);
+        assert_eq!(run("01M", 0.0, 0.0), [f32::INFINITY]);
+    }
+
+    #[test]
+    fn negation_flips_sign_of_zero() {
+        // `!` is arithmetic negation rather than logical not
+        assert_eq!(bits(&run("0!", 0.0, 0.0)), bits(&[-0.0]));
+        assert_eq!(bits(&run("0!!", 0.0, 0.0)), bits(&[0.0]));
+        assert_eq!(bits(&run("2!", -0.0, 0.0)), bits(&[0.0]));
+        assert_eq!(run("1!", 0.0, 0.0), [f32::NEG_INFINITY]);
+    }
+
+    #[test]
+    fn call_copies_arguments_without_popping() {
+        let disassembler = disassemble("", ":f:23456789;");
+        let mut emulator = Emulator::new(disassembler.functions());
+        for value in 1..=9 {
+            emulator.push(value as f32).unwrap();
+        }
+        emulator.call("f").unwrap();
+
+        // only the top 8 slots are copied, with param_0 the top of the stack
+        let expected: Vec<f32> = (1..=9).chain((2..=9).rev()).map(|v| v as f32).collect();
+        assert_eq!(emulator.stack(), &expected);
+    }
+
+    #[test]
+    fn call_on_short_stack_leaves_arguments_zero() {
+        let disassembler = disassemble("", ":f:23;");
+        let mut emulator = Emulator::new(disassembler.functions());
+        emulator.push(f32::INFINITY).unwrap();
+        emulator.call("f").unwrap();
+        assert_eq!(emulator.stack(), &[f32::INFINITY, f32::INFINITY, 0.0]);
+    }
+
+    #[test]
+    fn stack_underflow_is_an_error() {
+        // the VM would read below the bottom of its stack instead
+        let disassembler = disassemble("0+", "");
+        let mut emulator = Emulator::new(disassembler.functions());
+        assert!(emulator.run("t", &[0.0; NUM_ARGS]).is_err());
+        assert!(emulator.pop().is_err());
+    }
+
+    #[test]
+    fn stored_value_is_read_back() {
+        let disassembler = disassemble("a=23-;b=a!;ab", "");
+        let mut emulator = Emulator::new(disassembler.functions());
+        let mut args = [0.0; NUM_ARGS];
+        args[1] = f32::INFINITY;
+        emulator.run("t", &args).unwrap();
+        assert_eq!(emulator.stack(), &[f32::INFINITY, f32::NEG_INFINITY]);
+        assert_eq!(emulator.variable('a'), f32::INFINITY);
+        assert_eq!(emulator.variable('b'), f32::NEG_INFINITY);
+        assert_eq!(emulator.variable('c'), 0.0);
+    }
+
+    #[test]
+    fn store_ending_in_call_stores_its_result() {
+        let disassembler = disassemble("a=01^f;a", ":f:2!;");
+        let mut emulator = Emulator::new(disassembler.functions());
+        emulator.run("t", &[0.0; NUM_ARGS]).unwrap();
+        assert_eq!(emulator.stack(), &[0.0, f32::INFINITY, f32::NEG_INFINITY]);
+        assert_eq!(emulator.variable('a'), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn unbounded_recursion_is_an_error() {
+        let disassembler = disassemble("^r", ":r:^r;");
+        let mut emulator = Emulator::new(disassembler.functions());
+        let error = emulator.run("t", &[0.0; NUM_ARGS]).unwrap_err();
+        assert!(error.to_string().contains("max call depth"), "{error}");
+
+        // calls within the limit still run
+        let disassembler = disassemble("^f", ":g:0;:f:^g^g;");
+        let mut emulator = Emulator::new(disassembler.functions());
+        emulator.run("t", &[0.0; NUM_ARGS]).unwrap();
+        assert_eq!(emulator.stack(), &[0.0, 0.0]);
+    }
+
+    #[test]
+    fn reserved_letter_is_skipped() {
+        let disassembler = disassemble("1A0", "");
+        let mnemonics: Vec<String> = disassembler.functions()[0]
+            .instructions()
+            .iter()
+            .map(|instr| instr.mnemonic().to_string())
+            .collect();
+        assert_eq!(mnemonics, ["push", "unknown", "push", "ret"]);
+        assert_eq!(run("1A0", 0.0, 0.0), [f32::INFINITY, 0.0]);
+    }
+}
@@ -0,0 +1,27 @@
+/// The values a 2-bit code can decode to, indexed by the code.
+pub const VALUES: [f32; 4] = [0.0, f32::INFINITY, -0.0, f32::NEG_INFINITY];
+
+/// Decodes a 2-bit code into a float, as `b2f` does. Bit 0 selects an
+/// infinity and bit 1 selects a negative sign.
+pub fn b2f(code: u8) -> f32 {
+    VALUES[(code & 3) as usize]
+}
+
+/// Encodes a float as a 2-bit code, as `f2b` does. Bit 0 is set if the value
+/// is not finite and bit 1 is set if its sign bit is set.
+pub fn f2b(value: f32) -> u8 {
+    (!value.is_finite() as u8) | ((value.is_sign_negative() as u8) << 1)
+}
+
+/// Writes a value concisely, e.g. `-inf` or `-0`.
+pub fn format_value(value: f32) -> String {
+    if value.is_nan() {
+        String::from("NaN")
+    } else if value == 0.0 {
+        String::from(if value.is_sign_negative() { "-0" } else { "0" })
+    } else if value.is_infinite() {
+        String::from(if value < 0.0 { "-inf" } else { "inf" })
+    } else {
+        value.to_string()
+    }
+}
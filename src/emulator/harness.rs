@@ -0,0 +1,208 @@
+use std::error::Error;
+
+use crate::disassembler::function::Function;
+
+use super::{
+    dialect::VmDialect,
+    emulator::{Emulator, NUM_ARGS},
+    encoding::{b2f, f2b},
+};
+
+/// Value of the chain for the first chunk of the flag.
+pub const CHAIN_START: u32 = 0x5f3759df;
+
+/// Global variables the bytes of the chain are loaded into, from the least
+/// significant byte. `m` is skipped as it is an opcode.
+pub const CHAIN_VARIABLES: [char; 16] = [
+    'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'n', 'o', 'p', 'q',
+];
+
+/// The encrypted flag followed by the checksum of each chunk, as given in
+/// the comment at the end of `beyond.c`.
+pub const CIPHERTEXT: &str = "fa8c3453029e0e236800c26cc9d1748d\
+                              de9e98c1e24804cb9602b68a4fd424fc\
+                              7d7dca9fb3b4f6102ce5d3fb70cf4af0\
+                              d1204c1df031fb0ec583c5108fca9790\
+                              4d35b6b60183cac9b6183e4e4dc94d4d\
+                              8fc9356d";
+
+/// Unpacks a byte into four floats, as `c2f` does. The first float holds the
+/// most significant two bits.
+pub fn c2f(byte: u8) -> [f32; 4] {
+    [
+        b2f(byte >> 6),
+        b2f((byte >> 4) & 3),
+        b2f((byte >> 2) & 3),
+        b2f(byte & 3),
+    ]
+}
+
+/// Returns the arguments the harness runs the algorithm with for a chunk.
+pub fn chunk_args(bytes: [u8; 2]) -> [f32; NUM_ARGS] {
+    let mut args = [0.0; NUM_ARGS];
+    args[..4].copy_from_slice(&c2f(bytes[1]));
+    args[4..].copy_from_slice(&c2f(bytes[0]));
+    args
+}
+
+/// Returns the values of the chain variables for a chain, in the order of
+/// `CHAIN_VARIABLES`.
+pub fn chain_values(chain: u32) -> [f32; 16] {
+    let mut values = [0.0; 16];
+    for (i, byte) in chain.to_le_bytes().iter().enumerate() {
+        values[4 * i..4 * i + 4].copy_from_slice(&c2f(*byte));
+    }
+    values
+}
+
+/// Pops four floats and packs them into a byte, as `cpop` does. The top of
+/// the stack holds the least significant two bits.
+pub fn cpop(emulator: &mut Emulator) -> Result<u8, Box<dyn Error>> {
+    let mut byte = 0;
+    for i in 0..4 {
+        byte |= f2b(emulator.pop()?) << (2 * i);
+    }
+    Ok(byte)
+}
+
+/// Splits a hex ciphertext into the encrypted flag and the checksum of each
+/// chunk. There is one checksum for every two bytes of the flag. Like the
+/// harness, which includes the NUL terminator in the flag, a flag of odd
+/// length ends with a byte which is not encrypted and has no checksum.
+pub fn parse_ciphertext(hex: &str) -> Result<(Vec<u8>, Vec<u8>), Box<dyn Error>> {
+    let hex: Vec<char> = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    if !hex.len().is_multiple_of(2) {
+        return Err(format!("Ciphertext has an odd number of hex digits, {}", hex.len()).into());
+    }
+    let bytes = hex
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(&pair.iter().collect::<String>(), 16))
+        .collect::<Result<Vec<u8>, _>>()?;
+
+    // a flag of length n is followed by n / 2 checksums
+    let flag_len = match bytes.len() % 3 {
+        0 => bytes.len() / 3 * 2,
+        1 => (2 * bytes.len() + 1) / 3,
+        _ => {
+            return Err(format!(
+                "Ciphertext has {} bytes, which is not a flag followed by its checksums",
+                bytes.len()
+            )
+            .into())
+        }
+    };
+    Ok((bytes[..flag_len].to_vec(), bytes[flag_len..].to_vec()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncryptedChunk {
+    bytes: [u8; 2],
+    checksum: u8,
+    chain: u32,
+}
+
+impl EncryptedChunk {
+    /// Returns the encrypted bytes.
+    pub fn bytes(&self) -> [u8; 2] {
+        self.bytes
+    }
+
+    /// Returns the checksum of the chunk.
+    pub fn checksum(&self) -> u8 {
+        self.checksum
+    }
+
+    /// Returns the chain to encrypt the next chunk with.
+    pub fn chain(&self) -> u32 {
+        self.chain
+    }
+}
+
+/// Port of `encrypt_byte`, which runs the algorithm on the emulator.
+#[derive(Debug)]
+pub struct Harness<'a> {
+    emulator: Emulator<'a>,
+    function_id: String,
+}
+
+impl<'a> Harness<'a> {
+    /// Creates a new harness which encrypts with the function with the given
+    /// ID, usually the algorithm snippet.
+    pub fn new(functions: &'a [Function], function_id: String) -> Harness<'a> {
+        Harness {
+            emulator: Emulator::new(functions),
+            function_id,
+        }
+    }
+
+    /// Sets the dialect of the VM the algorithm is run on, by default that
+    /// of the reference build.
+    pub fn set_dialect(&mut self, dialect: VmDialect) {
+        self.emulator.set_dialect(dialect);
+    }
+
+    /// Encrypts a chunk of two bytes of the flag.
+    pub fn encrypt_chunk(
+        &mut self,
+        bytes: [u8; 2],
+        chain: u32,
+    ) -> Result<EncryptedChunk, Box<dyn Error>> {
+        self.emulator.reset();
+        for (name, value) in CHAIN_VARIABLES.iter().zip(chain_values(chain)) {
+            self.emulator.set_variable(*name, value);
+        }
+        self.emulator.run(&self.function_id, &chunk_args(bytes))?;
+
+        let checksum = cpop(&mut self.emulator)?;
+        let second = cpop(&mut self.emulator)?;
+        let first = cpop(&mut self.emulator)?;
+        let mut chain = 0;
+        for _ in 0..4 {
+            chain = (chain << 8) | cpop(&mut self.emulator)? as u32;
+        }
+
+        Ok(EncryptedChunk {
+            bytes: [first, second],
+            checksum,
+            chain,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{c2f, chain_values, chunk_args, parse_ciphertext, CHAIN_START};
+
+    #[test]
+    fn bytes_unpack_from_most_significant_bits() {
+        let (inf, zero) = (f32::INFINITY, 0.0f32);
+        let values = c2f(0b00_01_10_11);
+        let expected = [zero, inf, -zero, -inf];
+        assert_eq!(values.map(f32::to_bits), expected.map(f32::to_bits));
+
+        // the second byte of a chunk is in the first arguments
+        let args = chunk_args([0x12, 0x34]);
+        assert_eq!(args[..4], c2f(0x34));
+        assert_eq!(args[4..], c2f(0x12));
+
+        // the chain is loaded from its least significant byte
+        let chain = chain_values(CHAIN_START);
+        for (i, byte) in CHAIN_START.to_le_bytes().iter().enumerate() {
+            assert_eq!(chain[4 * i..4 * i + 4], c2f(*byte));
+        }
+    }
+
+    #[test]
+    fn odd_flag_ends_with_unencrypted_byte() {
+        let (flag, checksums) = parse_ciphertext("01020304").unwrap();
+        assert_eq!(flag, [1, 2, 3]);
+        assert_eq!(checksums, [4]);
+
+        let (flag, checksums) = parse_ciphertext("010203").unwrap();
+        assert_eq!(flag, [1, 2]);
+        assert_eq!(checksums, [3]);
+
+        assert!(parse_ciphertext("0102030405").is_err());
+        assert!(parse_ciphertext("01020").is_err());
+    }
+}
@@ -0,0 +1,5 @@
+pub mod dialect;
+#[allow(clippy::module_inception)]
+pub mod emulator;
+pub mod encoding;
+pub mod harness;
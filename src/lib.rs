@@ -0,0 +1,18 @@
+//! Disassembler, symbolic execution engine and analyses for the bytecode of
+//! the Beyond VM.
+
+pub mod analysis;
+pub mod assembler;
+pub mod disassembler;
+pub mod emulator;
+pub mod symbolic;
+
+pub use analysis::{call_graph::CallGraph, xrefs::Xrefs};
+pub use disassembler::{
+    disassembler::Disassembler,
+    error::{DisassemblyError, DisassemblyWarning},
+    function::Function,
+    instruction::{Instruction, Mnemonic},
+    operand::Operand,
+};
+pub use symbolic::{state_manager::StateManager, symbol::Symbol};
@@ -1,29 +1,446 @@
-use std::fs;
+use std::{collections::HashMap, env, error::Error, fs, path::Path, process};
 
-use disassembler::operand::Variable;
-use symbolic::state_manager::StateManager;
+use beyond_vm_disassembler::{
+    analysis::{
+        call_graph::CallGraph,
+        circuit::Circuit,
+        gates::{gate_name, recognise, Lane, MAX_GATE_INPUTS},
+        solver::Solver,
+        truth_table::TruthTable,
+        xrefs::Xrefs,
+    },
+    assembler::assembler::Assembler,
+    disassembler::{operand::Variable, stack_effect::StackEffect},
+    emulator::{
+        emulator::{Emulator, NUM_ARGS, NUM_VARIABLES},
+        encoding::format_value,
+        harness::{parse_ciphertext, CIPHERTEXT},
+    },
+    symbolic::{printer::Printer, state::CallMode, summary::SummaryCache},
+    Disassembler, StateManager,
+};
+use cli::options::{Calls, Command, Options, USAGE};
 
-use crate::disassembler::disassembler::Disassembler;
+mod cli;
 
-pub mod disassembler;
-pub mod symbolic;
+/// Default path of the disassembly listing.
+const DISASSEMBLY_PATH: &str = "output/disassembly.txt";
+
+/// Default directory assembled bytecode is written to.
+const OUTPUT_DIRECTORY: &str = "output";
+
+/// ID of the function the algorithm snippet is disassembled into.
+const ALGORITHM_ID: &str = "algorithm";
 
 fn main() {
-    let bytecode = fs::read_to_string("input/bytecode.txt").expect("Failed to read bytecode");
-    let algo_bytecode = fs::read_to_string("input/algo.txt").expect("Failed to read algo bytecode");
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match Options::parse(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(&options) {
+        eprintln!("Error: {e}");
+        process::exit(1);
+    }
+}
+
+/// Runs the command given on the command line.
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    match options.command() {
+        Command::Help => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        // assembling does not disassemble anything, which would need the
+        // bytecode being patched
+        Command::Assemble => assemble(options),
+        command => {
+            let mut disassembler = disassemble(options)?;
+            let stack_effects = match disassembler.infer_stack_effects() {
+                Ok(stack_effects) => stack_effects,
+                Err(e) if !command.needs_stack_effects() => {
+                    eprintln!("Warning: assuming every argument is a parameter: {e}");
+                    HashMap::new()
+                }
+                Err(e) => return Err(e),
+            };
+            match command {
+                Command::Disasm => disasm(options, &disassembler),
+                Command::Annotate => annotate(options, &disassembler, &stack_effects),
+                Command::Symex => symex(options, &disassembler, &stack_effects),
+                Command::Emulate => emulate(options, &disassembler),
+                Command::TruthTable => truth_tables(options, &disassembler, &stack_effects),
+                Command::Gates => gates(options, &mut disassembler, &stack_effects),
+                Command::Circuit => circuit(options, &disassembler, &stack_effects),
+                Command::Solve => solve(options, &disassembler),
+                Command::CallGraph => call_graph(options, &disassembler),
+                Command::Xrefs => xrefs(options, &disassembler),
+                Command::Help | Command::Assemble => unreachable!(),
+            }
+        }
+    }
+}
 
+/// Disassembles the bytecode and the algorithm snippet, reporting any
+/// errors recovered from and any warnings.
+fn disassemble(options: &Options) -> Result<Disassembler, Box<dyn Error>> {
+    let bytecode = read(options.bytecode())?;
     let mut disassembler = Disassembler::new(bytecode);
-    disassembler.disassemble();
-    disassembler.disassemble_snippet("algorithm".to_string(), algo_bytecode);
+    disassembler.set_recover(options.recover());
+    disassembler.disassemble()?;
+    if let Some(path) = options.algo() {
+        disassembler.disassemble_snippet(ALGORITHM_ID.to_string(), read(path)?)?;
+    }
 
-    let disassembly = disassembler.get_disassembly();
-    fs::write("output/disassembly.txt", disassembly.join("\n"))
-        .expect("Failed to write disassembly to file");
+    for diagnostic in disassembler.diagnostics() {
+        eprintln!("{diagnostic}");
+    }
+    for warning in disassembler.warnings() {
+        eprintln!("Warning: {warning}");
+    }
+    Ok(disassembler)
+}
 
-    // symbolically execute each function
+/// Writes the disassembly of the selected functions.
+fn disasm(options: &Options, disassembler: &Disassembler) -> Result<(), Box<dyn Error>> {
+    let dis = disassembler.get_selected_disassembly(|id| options.selects(id));
+    write_output(options, Some(DISASSEMBLY_PATH), &dis.join("\n"))
+}
+
+/// Writes the disassembly of the selected functions annotated with stack
+/// depths. The algorithm runs on an empty stack, other functions are assumed
+/// to be called with every slot they pop.
+fn annotate(
+    options: &Options,
+    disassembler: &Disassembler,
+    stack_effects: &HashMap<String, StackEffect>,
+) -> Result<(), Box<dyn Error>> {
+    let mut report = String::new();
     for function in disassembler.functions() {
-        println!("*** Symbolically executing {} ***\n", function.name());
-        let mut manager = StateManager::new(function);
-        manager.explore();
+        if !options.selects(function.id()) {
+            continue;
+        }
+        let entry_depth = match (function.id(), stack_effects.get(function.id())) {
+            (ALGORITHM_ID, _) => 0,
+            (_, Some(effect)) => effect.consumed(),
+            (_, None) => {
+                eprintln!("Skipping {}: its stack effect is unknown", function.name());
+                continue;
+            }
+        };
+        match function.get_annotated_disassembly(stack_effects, entry_depth) {
+            Ok(dis) => report.push_str(&format!("{}\n\n", dis.join("\n"))),
+            Err(e) => eprintln!("Skipping {}: {e}", function.name()),
+        }
+    }
+    write_output(options, None, &report)
+}
+
+/// Symbolically executes the selected functions, reusing the summaries of
+/// callees, and writes the stack each path returns with.
+fn symex(
+    options: &Options,
+    disassembler: &Disassembler,
+    stack_effects: &HashMap<String, StackEffect>,
+) -> Result<(), Box<dyn Error>> {
+    let mut summary_cache = SummaryCache::new(disassembler.functions(), stack_effects);
+    summary_cache.set_dialect(options.dialect());
+    let mut printer = Printer::new();
+    printer.set_line_width(options.width());
+    printer.set_bind_shared(options.bind_shared());
+    let mut report = String::new();
+
+    for function in disassembler.functions() {
+        if !options.selects(function.id()) {
+            continue;
+        }
+        report.push_str(&format!(
+            "*** Symbolically executing {} ***\n\n",
+            function.name()
+        ));
+        let call_mode = match options.calls() {
+            Calls::Summary => {
+                summary_cache.summarise_callees(function)?;
+                CallMode::Summary(summary_cache.summaries())
+            }
+            Calls::Inline => CallMode::Inline,
+            Calls::Opaque => CallMode::Opaque,
+        };
+        let mut manager =
+            StateManager::new(function, disassembler.functions(), stack_effects, call_mode);
+        manager.set_dialect(options.dialect());
+        if let Err(e) = manager.explore() {
+            report.push_str(&format!(
+                "Failed to execute {}: {e}\n\n\n\n\n",
+                function.name()
+            ));
+            continue;
+        }
+        for state in manager.returned_states() {
+            let (bindings, elements) = printer.print_all(state.stack().elements());
+            if !options.bind_shared() && !bindings.is_empty() {
+                eprintln!(
+                    "Warning: binding shared expressions of {}, which are too long to write out",
+                    function.name()
+                );
+            }
+            report.push_str("Stack on function return:\n");
+            for binding in bindings {
+                report.push_str(&format!("{binding}\n"));
+            }
+            report.push_str(&format!("[{}]\n\n\n\n\n", elements.join(", ")));
+        }
+    }
+    write_output(options, None, &report)
+}
+
+/// Runs a function on the emulator, then writes the stack and every global
+/// variable which is no longer zero. Missing arguments are zero.
+fn emulate(options: &Options, disassembler: &Disassembler) -> Result<(), Box<dyn Error>> {
+    let id = &options.arguments()[0];
+    let mut args = [0.0; NUM_ARGS];
+    for (arg, value) in args.iter_mut().zip(&options.arguments()[1..]) {
+        *arg = value
+            .parse()
+            .map_err(|_| format!("Invalid argument {value:?}, expected e.g. 0 or -inf"))?;
+    }
+
+    // the algorithm is run by the harness on an empty stack, other functions
+    // are called with the arguments given pushed, the first on top
+    let mut emulator = Emulator::new(disassembler.functions());
+    emulator.set_dialect(options.dialect());
+    if id == ALGORITHM_ID {
+        emulator.run(id, &args)?;
+    } else {
+        for arg in args[..options.arguments().len() - 1].iter().rev() {
+            emulator.push(*arg)?;
+        }
+        emulator.call(id)?;
+    }
+
+    let stack: Vec<String> = emulator.stack().iter().map(|v| format_value(*v)).collect();
+    let mut report = format!("stack: [{}]\n", stack.join(", "));
+    for name in (0..NUM_VARIABLES as u32).filter_map(char::from_u32) {
+        let value = emulator.variable(name);
+        if value != 0.0 || value.is_sign_negative() {
+            report.push_str(&format!("global_{name} = {}\n", format_value(value)));
+        }
+    }
+    write_output(options, None, &report)
+}
+
+/// Writes the truth tables of the selected functions.
+fn truth_tables(
+    options: &Options,
+    disassembler: &Disassembler,
+    stack_effects: &HashMap<String, StackEffect>,
+) -> Result<(), Box<dyn Error>> {
+    let mut report = String::new();
+    for function in disassembler.functions() {
+        if !options.selects(function.id()) {
+            continue;
+        }
+        match TruthTable::build(
+            function,
+            disassembler.functions(),
+            stack_effects,
+            options.dialect(),
+        ) {
+            Ok(table) => report.push_str(&format!("{table}\n")),
+            Err(e) => eprintln!("Skipping {}: {e}", function.name()),
+        }
+    }
+    write_output(options, None, &report)
+}
+
+/// Recognises the boolean gate each function implements, then writes the
+/// disassembly with the recognised gates noted in comments. Functions with
+/// more inputs than a gate reads are skipped rather than enumerated.
+fn gates(
+    options: &Options,
+    disassembler: &mut Disassembler,
+    stack_effects: &HashMap<String, StackEffect>,
+) -> Result<(), Box<dyn Error>> {
+    let mut display_names = Vec::new();
+    for function in disassembler.functions() {
+        let table = match TruthTable::build_within(
+            function,
+            disassembler.functions(),
+            stack_effects,
+            options.dialect(),
+            MAX_GATE_INPUTS,
+        ) {
+            Ok(table) => table,
+            Err(e) => {
+                eprintln!("Skipping {}: {e}", function.name());
+                continue;
+            }
+        };
+        let gates = recognise(&table);
+        for (i, gate) in gates.iter().enumerate() {
+            eprintln!("{} out_{i}: {gate}", function.name());
+        }
+        if let Some(name) = gate_name(&gates) {
+            display_names.push((function.id().to_string(), name));
+        }
+    }
+
+    for (id, name) in display_names {
+        disassembler.set_display_name(&id, name);
+    }
+    write_output(
+        options,
+        Some(DISASSEMBLY_PATH),
+        &disassembler.get_disassembly().join("\n"),
+    )
+}
+
+/// Lifts a function to a boolean circuit, then writes its netlist and the
+/// inputs each output depends on, or exports it as BLIF.
+fn circuit(
+    options: &Options,
+    disassembler: &Disassembler,
+    stack_effects: &HashMap<String, StackEffect>,
+) -> Result<(), Box<dyn Error>> {
+    let id = &options.arguments()[0];
+    let function = disassembler
+        .functions()
+        .iter()
+        .find(|function| function.id() == id)
+        .ok_or(format!("Unknown function {id:?}"))?;
+    let circuit = Circuit::lift(
+        function,
+        disassembler.functions(),
+        stack_effects,
+        options.dialect(),
+    )?;
+
+    if options.blif() {
+        let path = format!("{OUTPUT_DIRECTORY}/func_{id}.blif");
+        return write_output(options, Some(&path), &circuit.to_blif());
+    }
+
+    let mut report = format!("{circuit}\n");
+    for (i, lanes) in circuit.outputs().iter().enumerate() {
+        for (lane, wire) in Lane::all().iter().zip(lanes) {
+            let support: Vec<String> = circuit
+                .support(*wire)
+                .iter()
+                .map(|bit| bit.to_string())
+                .collect();
+            report.push_str(&format!(
+                "out_{i}.{lane} depends on [{}]\n",
+                support.join(", ")
+            ));
+        }
+    }
+    write_output(options, None, &report)
+}
+
+/// Recovers the flag from its ciphertext, by default the one given in
+/// beyond.c.
+fn solve(options: &Options, disassembler: &Disassembler) -> Result<(), Box<dyn Error>> {
+    let ciphertext = options
+        .arguments()
+        .first()
+        .map_or(CIPHERTEXT, String::as_str);
+    let (encrypted, checksums) = parse_ciphertext(ciphertext)?;
+    let algorithm = disassembler
+        .functions()
+        .iter()
+        .find(|function| function.id() == ALGORITHM_ID)
+        .ok_or("Missing algorithm, which is needed to solve")?;
+    let mut solver = Solver::new(algorithm, disassembler.functions(), options.dialect())?;
+    let solution = solver.solve(&encrypted, &checksums)?;
+    for (i, candidates) in solution.candidates().iter().enumerate() {
+        if candidates.len() > 1 {
+            eprintln!(
+                "Chunk {i} has {} candidates, using the first",
+                candidates.len()
+            );
+        }
+    }
+    // the flag is NUL terminated
+    let flag: Vec<u8> = solution
+        .plaintext()
+        .iter()
+        .copied()
+        .take_while(|byte| *byte != 0)
+        .collect();
+    write_output(
+        options,
+        None,
+        &format!("{}\n", String::from_utf8_lossy(&flag)),
+    )
+}
+
+/// Exports the call graph of the functions, as Graphviz DOT by default.
+fn call_graph(options: &Options, disassembler: &Disassembler) -> Result<(), Box<dyn Error>> {
+    let graph = CallGraph::build(disassembler.functions());
+    let export = if options.json() {
+        graph.to_json()
+    } else {
+        graph.to_dot()
+    };
+    write_output(options, None, &export)
+}
+
+/// Writes the references to the given functions and variables, or to every
+/// one if none are given.
+fn xrefs(options: &Options, disassembler: &Disassembler) -> Result<(), Box<dyn Error>> {
+    let xrefs = Xrefs::build(disassembler.functions());
+    write_output(options, None, &xrefs.report(options.arguments()))
+}
+
+/// Turns a listing, by default the disassembly, back into bytecode. The
+/// bytecode and the algorithm snippet are written to the output directory.
+fn assemble(options: &Options) -> Result<(), Box<dyn Error>> {
+    let path = options
+        .arguments()
+        .first()
+        .map_or(DISASSEMBLY_PATH, String::as_str);
+    let mut assembler = Assembler::new(read(path)?);
+    assembler
+        .assemble()
+        .map_err(|e| format!("Failed to assemble {path}: {e}"))?;
+
+    let directory = Path::new(options.output().unwrap_or(OUTPUT_DIRECTORY));
+    fs::create_dir_all(directory)?;
+    fs::write(
+        directory.join("bytecode.txt"),
+        assembler.get_bytecode(&[ALGORITHM_ID]),
+    )?;
+    fs::write(
+        directory.join("algo.txt"),
+        assembler.get_snippet(ALGORITHM_ID).unwrap_or_default(),
+    )?;
+    Ok(())
+}
+
+/// Reads a file, naming it in the error.
+fn read(path: &str) -> Result<String, Box<dyn Error>> {
+    fs::read_to_string(path).map_err(|e| format!("Failed to read {path}: {e}").into())
+}
+
+/// Writes the result of a command to the output given on the command line,
+/// otherwise to the default path of the command. Without either, or if the
+/// output is `-`, it is written to standard output.
+fn write_output(
+    options: &Options,
+    default: Option<&str>,
+    contents: &str,
+) -> Result<(), Box<dyn Error>> {
+    match options.output().or(default) {
+        Some(path) if path != "-" => {
+            fs::write(path, contents).map_err(|e| format!("Failed to write {path}: {e}").into())
+        }
+        _ => {
+            print!("{contents}");
+            Ok(())
+        }
     }
 }
@@ -1,4 +1,7 @@
+pub mod printer;
+pub mod simplifier;
 pub mod stack;
 pub mod state;
 pub mod state_manager;
+pub mod summary;
 pub mod symbol;
@@ -0,0 +1,494 @@
+use std::collections::{HashMap, HashSet};
+
+use super::symbol::{BinaryOperator, LiteralSymbol, Symbol, SymbolKind};
+
+/// Precedence of expressions which are never parenthesised, such as
+/// identifiers and calls.
+const ATOMIC_PRECEDENCE: u8 = 4;
+
+/// Precedence of unary expressions, including negative literals.
+const UNARY_PRECEDENCE: u8 = 3;
+
+/// Maximum length of the expressions written without binding shared
+/// expressions. Writing a shared expression in full at each use can grow
+/// exponentially with the depth of the symbol, so longer expressions are
+/// written with bindings regardless.
+pub const MAX_UNBOUND_LENGTH: usize = 1 << 20;
+
+/// Writes symbols as readable expressions, with only the parentheses needed
+/// to keep their structure.
+///
+/// Binary operators are left associative, with `*` and `/` binding tighter
+/// than `+` and `-`. Floating point arithmetic is not associative, so a right
+/// operand of the same precedence is always parenthesised, e.g. `a - (b - c)`
+/// and `a + (b + c)`, whereas `(a - b) - c` is written `a - b - c`.
+#[derive(Debug, Clone)]
+pub struct Printer {
+    line_width: Option<usize>,
+    indent_width: usize,
+    bind_shared: bool,
+}
+
+impl Printer {
+    /// Creates a new printer, which writes each expression on a single line
+    /// without binding shared expressions.
+    pub fn new() -> Printer {
+        Printer {
+            line_width: None,
+            indent_width: 4,
+            bind_shared: false,
+        }
+    }
+
+    /// Sets the width expressions are wrapped at, or `None` to never wrap
+    /// them. Calls are wrapped with one argument per line, and binary
+    /// expressions with their operator starting a line.
+    pub fn set_line_width(&mut self, line_width: Option<usize>) {
+        self.line_width = line_width;
+    }
+
+    /// Sets the number of spaces each level of wrapped expressions is
+    /// indented by.
+    pub fn set_indent_width(&mut self, indent_width: usize) {
+        self.indent_width = indent_width;
+    }
+
+    /// Sets whether expressions which are shared between or within the
+    /// symbols written by `print_all` are bound to temporaries.
+    pub fn set_bind_shared(&mut self, bind_shared: bool) {
+        self.bind_shared = bind_shared;
+    }
+
+    /// Writes a symbol. A symbol which would be longer than
+    /// `MAX_UNBOUND_LENGTH` is written with its bindings first, one per line.
+    pub fn print(&self, symbol: &Symbol) -> String {
+        let (mut lines, expressions) = self.print_all(std::slice::from_ref(symbol));
+        lines.extend(expressions);
+        lines.join("\n")
+    }
+
+    /// Writes a group of symbols. If shared expressions are bound, or the
+    /// symbols would be longer than `MAX_UNBOUND_LENGTH` otherwise, every
+    /// expression that is shared is bound to a temporary so that it is only
+    /// written once. The temporaries are returned first, one per binding, as
+    /// `let tN = ...;`.
+    pub fn print_all(&self, roots: &[Symbol]) -> (Vec<String>, Vec<String>) {
+        let bound = if self.bind_shared || unbound_length(roots) > MAX_UNBOUND_LENGTH {
+            shared_expressions(roots)
+        } else {
+            Vec::new()
+        };
+        let names: HashMap<Symbol, String> = bound
+            .iter()
+            .map(|symbol| (symbol.clone(), format!("t{}", symbol.id())))
+            .collect();
+        let mut context = Context::new(self, names);
+
+        let bindings = bound
+            .iter()
+            .map(|symbol| {
+                let name = context.names[symbol].clone();
+                let prefix = format!("let {name} = ");
+                let expression = context.write(symbol, true, 0);
+                format!("{prefix}{expression};")
+            })
+            .collect();
+        let expressions = roots
+            .iter()
+            .map(|root| context.write(root, false, 0))
+            .collect();
+
+        (bindings, expressions)
+    }
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns every expression which is referenced more than once within the
+/// given symbols, in order of creation, which means each only refers to
+/// earlier ones.
+fn shared_expressions(roots: &[Symbol]) -> Vec<Symbol> {
+    // count how many times each expression is referenced
+    let mut references: HashMap<Symbol, usize> = HashMap::new();
+    let mut visited = HashSet::new();
+    let mut worklist: Vec<Symbol> = roots.to_vec();
+    for root in roots {
+        *references.entry(root.clone()).or_insert(0) += 1;
+    }
+    while let Some(symbol) = worklist.pop() {
+        if !visited.insert(symbol.clone()) {
+            continue;
+        }
+        for child in symbol.children() {
+            *references.entry(child.clone()).or_insert(0) += 1;
+            worklist.push(child);
+        }
+    }
+
+    let mut shared: Vec<Symbol> = visited
+        .into_iter()
+        .filter(|symbol| {
+            references.get(symbol).copied().unwrap_or(0) > 1 && !symbol.children().is_empty()
+        })
+        .collect();
+    shared.sort();
+    shared
+}
+
+/// Returns an upper bound on the length of the given symbols when written
+/// without bindings, counting shared expressions at each use.
+fn unbound_length(roots: &[Symbol]) -> usize {
+    let mut lengths = HashMap::new();
+    roots.iter().fold(0, |total, root| {
+        total.saturating_add(symbol_length(root, &mut lengths))
+    })
+}
+
+/// Returns an upper bound on the length of a symbol written without
+/// bindings, allowing for parentheses around each operand.
+fn symbol_length(symbol: &Symbol, lengths: &mut HashMap<Symbol, usize>) -> usize {
+    if let Some(length) = lengths.get(symbol) {
+        return *length;
+    }
+    let length = match symbol.kind() {
+        SymbolKind::LiteralSymbol(literal) => literal.to_string().len(),
+        SymbolKind::IdentifierSymbol(ident) => ident.to_string().len(),
+        SymbolKind::UnaryExpressionSymbol(unary) => {
+            symbol_length(&unary.argument(), lengths).saturating_add(3)
+        }
+        SymbolKind::BinaryExpressionSymbol(binary) => symbol_length(&binary.left(), lengths)
+            .saturating_add(symbol_length(&binary.right(), lengths))
+            .saturating_add(7),
+        SymbolKind::CallExpressionSymbol(call) => call.arguments().iter().fold(
+            symbol_length(&call.callee(), lengths).saturating_add(2),
+            |length, arg| {
+                length
+                    .saturating_add(symbol_length(arg, lengths))
+                    .saturating_add(2)
+            },
+        ),
+        SymbolKind::CallResultSymbol(result) => symbol_length(&result.call(), lengths)
+            .saturating_add(result.index().to_string().len() + 2),
+    };
+    lengths.insert(symbol.clone(), length);
+    length
+}
+
+/// State while writing symbols: the names bound to shared expressions, and
+/// the single line form of each expression written so far.
+struct Context<'a> {
+    printer: &'a Printer,
+    names: HashMap<Symbol, String>,
+    flat: HashMap<Symbol, String>,
+}
+
+impl<'a> Context<'a> {
+    /// Creates a new context with the given names.
+    fn new(printer: &'a Printer, names: HashMap<Symbol, String>) -> Context<'a> {
+        Context {
+            printer,
+            names,
+            flat: HashMap::new(),
+        }
+    }
+
+    /// Returns the precedence of a symbol as it is written, where a named
+    /// symbol is atomic. The top level symbol is expanded rather than named
+    /// if `expand` is set.
+    fn precedence(&self, symbol: &Symbol, expand: bool) -> u8 {
+        if !expand && self.names.contains_key(symbol) {
+            return ATOMIC_PRECEDENCE;
+        }
+        match symbol.kind() {
+            SymbolKind::BinaryExpressionSymbol(binary) => match binary.operator() {
+                BinaryOperator::Add | BinaryOperator::Subtract => 1,
+                BinaryOperator::Multiply | BinaryOperator::Divide => 2,
+            },
+            SymbolKind::UnaryExpressionSymbol(_)
+            | SymbolKind::LiteralSymbol(LiteralSymbol::NegZero | LiteralSymbol::NegInfinity) => {
+                UNARY_PRECEDENCE
+            }
+            _ => ATOMIC_PRECEDENCE,
+        }
+    }
+
+    /// Writes a symbol on a single line.
+    fn write_flat(&mut self, symbol: &Symbol, expand: bool) -> String {
+        if !expand {
+            if let Some(name) = self.names.get(symbol) {
+                return name.clone();
+            }
+            if let Some(flat) = self.flat.get(symbol) {
+                return flat.clone();
+            }
+        }
+
+        let flat = match symbol.kind() {
+            SymbolKind::LiteralSymbol(literal) => literal.to_string(),
+            SymbolKind::IdentifierSymbol(ident) => ident.to_string(),
+            SymbolKind::UnaryExpressionSymbol(unary) => {
+                let argument = self.write_flat(&unary.argument(), false);
+                let argument = self.wrap_flat(&unary.argument(), argument, UNARY_PRECEDENCE + 1);
+                format!("{}{argument}", unary.operator())
+            }
+            SymbolKind::BinaryExpressionSymbol(binary) => {
+                let precedence = self.precedence(symbol, true);
+                let left = self.write_flat(&binary.left(), false);
+                let left = self.wrap_flat(&binary.left(), left, precedence);
+                let right = self.write_flat(&binary.right(), false);
+                let right = self.wrap_flat(&binary.right(), right, precedence + 1);
+                format!("{left} {} {right}", binary.operator())
+            }
+            SymbolKind::CallExpressionSymbol(call) => {
+                let callee = self.write_flat(&call.callee(), false);
+                let arguments: Vec<String> = call
+                    .arguments()
+                    .iter()
+                    .map(|arg| self.write_flat(arg, false))
+                    .collect();
+                format!("{callee}({})", arguments.join(", "))
+            }
+            SymbolKind::CallResultSymbol(result) => {
+                let call = self.write_flat(&result.call(), false);
+                format!("{call}[{}]", result.index())
+            }
+        };
+        if !expand {
+            self.flat.insert(symbol.clone(), flat.clone());
+        }
+        flat
+    }
+
+    /// Parenthesises a written operand if it binds less tightly than the
+    /// given precedence.
+    fn wrap_flat(&self, operand: &Symbol, written: String, precedence: u8) -> String {
+        if self.precedence(operand, false) < precedence {
+            format!("({written})")
+        } else {
+            written
+        }
+    }
+
+    /// Writes a symbol, wrapping it over several lines if it does not fit
+    /// within the line width when it starts at the given depth of
+    /// indentation.
+    fn write(&mut self, symbol: &Symbol, expand: bool, depth: usize) -> String {
+        let flat = self.write_flat(symbol, expand);
+        let Some(line_width) = self.printer.line_width else {
+            return flat;
+        };
+        if depth * self.printer.indent_width + flat.len() <= line_width
+            || (!expand && self.names.contains_key(symbol))
+        {
+            return flat;
+        }
+
+        match symbol.kind() {
+            SymbolKind::UnaryExpressionSymbol(unary) => {
+                let argument =
+                    self.write_operand(&unary.argument(), UNARY_PRECEDENCE + 1, depth, false);
+                format!("{}{argument}", unary.operator())
+            }
+            SymbolKind::BinaryExpressionSymbol(binary) => {
+                let precedence = self.precedence(symbol, true);
+                let left = self.write_operand(&binary.left(), precedence, depth, false);
+                let right = self.write_operand(&binary.right(), precedence + 1, depth, true);
+                format!(
+                    "{left}\n{}{} {right}",
+                    self.indent(depth),
+                    binary.operator()
+                )
+            }
+            SymbolKind::CallExpressionSymbol(call) => {
+                let callee = self.write_flat(&call.callee(), false);
+                let arguments: Vec<String> = call
+                    .arguments()
+                    .iter()
+                    .map(|arg| {
+                        format!(
+                            "{}{}",
+                            self.indent(depth + 1),
+                            self.write(arg, false, depth + 1)
+                        )
+                    })
+                    .collect();
+                format!(
+                    "{callee}(\n{}\n{})",
+                    arguments.join(",\n"),
+                    self.indent(depth)
+                )
+            }
+            SymbolKind::CallResultSymbol(result) => {
+                let call = self.write(&result.call(), false, depth);
+                format!("{call}[{}]", result.index())
+            }
+            _ => flat,
+        }
+    }
+
+    /// Writes an operand, wrapping it over several lines if needed. An
+    /// operand which needs parentheses is wrapped inside them. Otherwise, if
+    /// the operand is `hanging`, such as the right operand of a binary
+    /// expression, its lines are indented under the line it starts on.
+    fn write_operand(
+        &mut self,
+        operand: &Symbol,
+        precedence: u8,
+        depth: usize,
+        hanging: bool,
+    ) -> String {
+        if self.precedence(operand, false) >= precedence {
+            return self.write(operand, false, depth + hanging as usize);
+        }
+        let flat = self.write_flat(operand, false);
+        if let Some(line_width) = self.printer.line_width {
+            if depth * self.printer.indent_width + flat.len() + 2 > line_width {
+                let inner = self.write(operand, false, depth + 1);
+                return format!(
+                    "(\n{}{inner}\n{})",
+                    self.indent(depth + 1),
+                    self.indent(depth)
+                );
+            }
+        }
+        format!("({flat})")
+    }
+
+    /// Returns the indentation at a depth.
+    fn indent(&self, depth: usize) -> String {
+        " ".repeat(depth * self.printer.indent_width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Printer, MAX_UNBOUND_LENGTH};
+    use crate::symbolic::symbol::{
+        BinaryExpressionSymbol, BinaryOperator, CallExpressionSymbol, IdentifierSymbol,
+        LiteralSymbol, Symbol, UnaryExpressionSymbol, UnaryOperator,
+    };
+
+    /// Returns an identifier symbol.
+    fn ident(name: &str) -> Symbol {
+        Symbol::from(IdentifierSymbol::new(name.to_string()))
+    }
+
+    /// Returns a binary expression symbol.
+    fn binary(operator: BinaryOperator, left: Symbol, right: Symbol) -> Symbol {
+        Symbol::from(BinaryExpressionSymbol::new(operator, left, right))
+    }
+
+    /// Returns `-argument`.
+    fn negate(argument: Symbol) -> Symbol {
+        Symbol::from(UnaryExpressionSymbol::new(UnaryOperator::Negate, argument))
+    }
+
+    /// Returns a call to `min` with the given arguments.
+    fn min(arguments: Vec<Symbol>) -> Symbol {
+        Symbol::from(CallExpressionSymbol::new(ident("min"), arguments))
+    }
+
+    #[test]
+    fn only_needed_parentheses_are_written() {
+        let (a, b, c) = (ident("a"), ident("b"), ident("c"));
+        let printer = Printer::new();
+        let sub = |left, right| binary(BinaryOperator::Subtract, left, right);
+        let add = |left, right| binary(BinaryOperator::Add, left, right);
+        let mul = |left, right| binary(BinaryOperator::Multiply, left, right);
+
+        assert_eq!(
+            printer.print(&sub(a.clone(), sub(b.clone(), c.clone()))),
+            "a - (b - c)"
+        );
+        assert_eq!(
+            printer.print(&sub(sub(a.clone(), b.clone()), c.clone())),
+            "a - b - c"
+        );
+        assert_eq!(
+            printer.print(&negate(add(a.clone(), b.clone()))),
+            "-(a + b)"
+        );
+        assert_eq!(printer.print(&negate(negate(a.clone()))), "-(-a)");
+        assert_eq!(
+            printer.print(&mul(add(a.clone(), b.clone()), c.clone())),
+            "(a + b) * c"
+        );
+        assert_eq!(printer.print(&add(a.clone(), mul(b, c))), "a + b * c");
+        // a negative operand binds tightly enough, but a negated one is
+        // parenthesised so that it is not written `--`
+        assert_eq!(
+            printer.print(&sub(a.clone(), Symbol::from(LiteralSymbol::NegInfinity))),
+            "a - -Infinity"
+        );
+        assert_eq!(
+            printer.print(&negate(Symbol::from(LiteralSymbol::NegInfinity))),
+            "-(-Infinity)"
+        );
+    }
+
+    #[test]
+    fn long_expressions_are_wrapped() {
+        let symbol = binary(
+            BinaryOperator::Add,
+            min(vec![ident("param_0"), ident("param_1")]),
+            ident("global_a"),
+        );
+        let mut printer = Printer::new();
+        assert_eq!(printer.print(&symbol), "min(param_0, param_1) + global_a");
+
+        printer.set_line_width(Some(24));
+        assert_eq!(printer.print(&symbol), "min(param_0, param_1)\n+ global_a");
+
+        printer.set_line_width(Some(16));
+        printer.set_indent_width(2);
+        assert_eq!(
+            printer.print(&symbol),
+            "min(\n  param_0,\n  param_1\n)\n+ global_a"
+        );
+    }
+
+    #[test]
+    fn shared_expressions_are_bound() {
+        let sum = binary(BinaryOperator::Add, ident("a"), ident("b"));
+        let roots = [
+            binary(BinaryOperator::Multiply, sum.clone(), sum.clone()),
+            negate(sum.clone()),
+        ];
+        let mut printer = Printer::new();
+        let (bindings, expressions) = printer.print_all(&roots);
+        assert!(bindings.is_empty());
+        assert_eq!(expressions, ["(a + b) * (a + b)", "-(a + b)"]);
+
+        printer.set_bind_shared(true);
+        let name = format!("t{}", sum.id());
+        let (bindings, expressions) = printer.print_all(&roots);
+        assert_eq!(bindings, [format!("let {name} = a + b;")]);
+        assert_eq!(
+            expressions,
+            [format!("{name} * {name}"), format!("-{name}")]
+        );
+    }
+
+    #[test]
+    fn long_expressions_are_bound_regardless() {
+        // each level uses the one below twice, doubling the unbound length
+        let mut symbol = ident("a");
+        for _ in 0..20 {
+            symbol = binary(BinaryOperator::Add, symbol.clone(), symbol);
+        }
+        let printer = Printer::new();
+        let (bindings, expressions) = printer.print_all(std::slice::from_ref(&symbol));
+        assert_eq!(bindings.len(), 19);
+        assert!(expressions[0].len() < 20);
+        assert!(printer.print(&symbol).len() < MAX_UNBOUND_LENGTH);
+
+        // shorter expressions are still written out
+        let (bindings, _) =
+            printer.print_all(&[binary(BinaryOperator::Add, ident("b"), ident("b"))]);
+        assert!(bindings.is_empty());
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+
+use crate::emulator::emulator::{fmax, fmin};
+
+use super::symbol::{
+    BinaryExpressionSymbol, BinaryOperator, CallExpressionSymbol, CallResultSymbol, LiteralSymbol,
+    Symbol, SymbolKind, UnaryExpressionSymbol, UnaryOperator,
+};
+
+/// Simplifies a symbol and all of its subexpressions.
+///
+/// Every value in the VM is 0.0, -0.0, Infinity, -Infinity or NaN, so the
+/// rewrites only need to hold on that domain rather than for all floats,
+/// and some do not hold for other finite values, e.g. `1 / (1 / x) = x`.
+/// The sign of NaN is not tracked. Identifiers are assumed to stand for
+/// values in the domain, although uninitialised slots of a short stack
+/// may not.
+pub fn simplify(symbol: Symbol) -> Symbol {
+    simplify_memoised(symbol, &mut HashMap::new())
+}
+
+/// Simplifies a symbol, visiting each shared subexpression only once.
+fn simplify_memoised(symbol: Symbol, memo: &mut HashMap<Symbol, Symbol>) -> Symbol {
+    if let Some(simplified) = memo.get(&symbol) {
+        return simplified.clone();
+    }
+
+    let simplified = match symbol.kind() {
+        SymbolKind::LiteralSymbol(_) | SymbolKind::IdentifierSymbol(_) => symbol.clone(),
+        SymbolKind::UnaryExpressionSymbol(unary) => {
+            simplify_unary(unary.operator(), simplify_memoised(unary.argument(), memo))
+        }
+        SymbolKind::BinaryExpressionSymbol(binary) => simplify_binary(
+            binary.operator(),
+            simplify_memoised(binary.left(), memo),
+            simplify_memoised(binary.right(), memo),
+        ),
+        SymbolKind::CallExpressionSymbol(call) => simplify_call(
+            simplify_memoised(call.callee(), memo),
+            call.arguments()
+                .iter()
+                .map(|arg| simplify_memoised(arg.clone(), memo))
+                .collect(),
+        ),
+        SymbolKind::CallResultSymbol(result) => Symbol::from(CallResultSymbol::new(
+            simplify_memoised(result.call(), memo),
+            result.index(),
+        )),
+    };
+
+    memo.insert(symbol, simplified.clone());
+    simplified
+}
+
+/// Simplifies a symbol whose subexpressions are already simplified, such as
+/// an expression built from operands on the stack. Only the symbol itself
+/// is rewritten.
+pub fn simplify_root(symbol: Symbol) -> Symbol {
+    match symbol.kind() {
+        SymbolKind::UnaryExpressionSymbol(unary) => {
+            simplify_unary(unary.operator(), unary.argument())
+        }
+        SymbolKind::BinaryExpressionSymbol(binary) => {
+            simplify_binary(binary.operator(), binary.left(), binary.right())
+        }
+        SymbolKind::CallExpressionSymbol(call) => {
+            simplify_call(call.callee(), call.arguments().clone())
+        }
+        _ => symbol,
+    }
+}
+
+/// Returns the literal a symbol is, if it is one.
+fn literal(symbol: &Symbol) -> Option<LiteralSymbol> {
+    match symbol.kind() {
+        SymbolKind::LiteralSymbol(literal) => Some(literal.clone()),
+        _ => None,
+    }
+}
+
+/// Returns whether a symbol is a given literal.
+fn is_literal(symbol: &Symbol, expected: LiteralSymbol) -> bool {
+    literal(symbol) == Some(expected)
+}
+
+/// Returns the symbol for a value, if it can be written as a literal.
+fn fold(value: f32) -> Option<Symbol> {
+    LiteralSymbol::from_value(value).map(Symbol::from)
+}
+
+/// Simplifies a unary expression whose argument is already simplified.
+fn simplify_unary(operator: &UnaryOperator, argument: Symbol) -> Symbol {
+    match operator {
+        UnaryOperator::Negate => {
+            if let Some(folded) =
+                literal(&argument).and_then(|lit| fold(operator.apply(lit.value())))
+            {
+                return folded;
+            }
+
+            // --x = x, since negation only flips the sign bit
+            if let SymbolKind::UnaryExpressionSymbol(inner) = argument.kind() {
+                if *inner.operator() == UnaryOperator::Negate {
+                    return inner.argument();
+                }
+            }
+        }
+    }
+
+    Symbol::from(UnaryExpressionSymbol::new(operator.clone(), argument))
+}
+
+/// Simplifies a binary expression whose operands are already simplified.
+fn simplify_binary(operator: &BinaryOperator, left: Symbol, right: Symbol) -> Symbol {
+    if let (Some(l), Some(r)) = (literal(&left), literal(&right)) {
+        if let Some(folded) = fold(operator.apply(l.value(), r.value())) {
+            return folded;
+        }
+    }
+
+    // NaN absorbs every arithmetic operation
+    if is_literal(&left, LiteralSymbol::NaN) || is_literal(&right, LiteralSymbol::NaN) {
+        return Symbol::from(LiteralSymbol::NaN);
+    }
+
+    match operator {
+        BinaryOperator::Add => {
+            // -0.0 is the additive identity, including for 0.0
+            if is_literal(&right, LiteralSymbol::NegZero) {
+                return left;
+            }
+            if is_literal(&left, LiteralSymbol::NegZero) {
+                return right;
+            }
+        }
+        BinaryOperator::Subtract => {
+            // x - 0.0 = x + -0.0 = x
+            if is_literal(&right, LiteralSymbol::Zero) {
+                return left;
+            }
+            // -0.0 - x = -x, including 0.0 - 0.0 = 0.0
+            if is_literal(&left, LiteralSymbol::NegZero) {
+                return simplify_unary(&UnaryOperator::Negate, right);
+            }
+        }
+        BinaryOperator::Multiply => {}
+        BinaryOperator::Divide => {
+            // 1 / (1 / x) = x for zeros, infinities and NaN
+            if is_literal(&left, LiteralSymbol::One) {
+                if let SymbolKind::BinaryExpressionSymbol(inner) = right.kind() {
+                    if let BinaryOperator::Divide = inner.operator() {
+                        if is_literal(&inner.left(), LiteralSymbol::One) {
+                            return inner.right();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Symbol::from(BinaryExpressionSymbol::new(operator.clone(), left, right))
+}
+
+/// Returns the name of the function a call expression calls, if it is
+/// called by name.
+fn callee_name(callee: &Symbol) -> Option<String> {
+    match callee.kind() {
+        SymbolKind::IdentifierSymbol(ident) => Some(ident.name().to_string()),
+        _ => None,
+    }
+}
+
+/// Simplifies a call expression whose callee and arguments are already
+/// simplified. Only calls to `min` and `max` are simplified.
+///
+/// Nested chains are flattened into a single call with many arguments,
+/// which is evaluated left to right. Like `fmin` and `fmax`, NaN arguments
+/// are ignored and ties (e.g. 0.0 and -0.0) go to the later argument, which
+/// makes the chain associative but not commutative, so the order of the
+/// arguments is kept.
+fn simplify_call(callee: Symbol, arguments: Vec<Symbol>) -> Symbol {
+    let name = callee_name(&callee);
+    let (absorbing, combine): (LiteralSymbol, fn(f32, f32) -> f32) = match name.as_deref() {
+        Some("min") => (LiteralSymbol::NegInfinity, fmin),
+        Some("max") => (LiteralSymbol::Infinity, fmax),
+        _ => return Symbol::from(CallExpressionSymbol::new(callee, arguments)),
+    };
+
+    let mut operands = Vec::new();
+    for arg in arguments {
+        match arg.kind() {
+            SymbolKind::CallExpressionSymbol(inner) if inner.callee() == callee => {
+                operands.extend(inner.arguments().iter().cloned())
+            }
+            _ => operands.push(arg),
+        }
+    }
+
+    operands.retain(|operand| !is_literal(operand, LiteralSymbol::NaN));
+    if operands.is_empty() {
+        return Symbol::from(LiteralSymbol::NaN);
+    }
+
+    if operands
+        .iter()
+        .any(|operand| is_literal(operand, absorbing.clone()))
+    {
+        return Symbol::from(absorbing);
+    }
+
+    // only the last occurrence of a repeated argument can win a tie
+    let mut deduplicated: Vec<Symbol> = Vec::new();
+    for (index, operand) in operands.iter().enumerate() {
+        if !operands[index + 1..].contains(operand) {
+            deduplicated.push(operand.clone());
+        }
+    }
+
+    let values: Option<Vec<f32>> = deduplicated
+        .iter()
+        .map(|operand| literal(operand).map(|lit| lit.value()))
+        .collect();
+    if let Some(values) = values {
+        let value = values.into_iter().reduce(combine).unwrap_or(f32::NAN);
+        if let Some(folded) = fold(value) {
+            return folded;
+        }
+    }
+
+    if deduplicated.len() == 1 {
+        return deduplicated.swap_remove(0);
+    }
+
+    Symbol::from(CallExpressionSymbol::new(callee, deduplicated))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::simplify;
+    use crate::{
+        disassembler::disassembler::Disassembler,
+        emulator::emulator::{Emulator, NUM_ARGS},
+        symbolic::{
+            state::CallMode,
+            state_manager::StateManager,
+            symbol::{IdentifierSymbol, Symbol, UnaryExpressionSymbol, UnaryOperator},
+        },
+    };
+
+    /// Values every rewrite is checked on: those the VM computes, and a
+    /// finite value standing for anything else.
+    const VALUES: [f32; 6] = [0.0, -0.0, f32::INFINITY, f32::NEG_INFINITY, f32::NAN, 1.0];
+
+    /// Function bodies exercising each rewrite, on the arguments `2` and `3`,
+    /// grouped by the rewrite they exercise.
+    const CASES: [(&str, &[&str]); 10] = [
+        ("folding literals", &["01*", "10!+", "0!'"]),
+        ("--x = x", &["2!!"]),
+        ("NaN absorbs", &["00/2+", "200/*"]),
+        ("-0.0 is the additive identity", &["20!+", "0!2+"]),
+        ("x + x is not x", &["22+"]),
+        ("x - 0.0 = x", &["02-"]),
+        ("-0.0 - x = -x", &["20!-", "2!0!-"]),
+        ("x / x is not NaN", &["22/"]),
+        ("1 / (1 / x) = x", &["2''"]),
+        (
+            "min and max chains",
+            &[
+                "23m2m", "23M3M", "232mm", "23m0m0!m", "230!mm", "21!m", "21M", "200/m", "00/00/M",
+                "23m3M2m",
+            ],
+        ),
+    ];
+
+    /// Returns whether two values are the same, telling -0.0 and 0.0 apart.
+    fn same(left: f32, right: f32) -> bool {
+        (left.is_nan() && right.is_nan()) || left.to_bits() == right.to_bits()
+    }
+
+    #[test]
+    fn rewrites_match_emulator() {
+        for (rewrite, bodies) in CASES {
+            for body in bodies {
+                check_matches_emulator(rewrite, body);
+            }
+        }
+    }
+
+    /// Checks that the symbol a function body leaves on top of the stack,
+    /// simplified once or twice, evaluates to what the emulator computes.
+    fn check_matches_emulator(rewrite: &str, body: &str) {
+        let mut disassembler = Disassembler::new(format!(":t:{body};"));
+        disassembler.disassemble().unwrap();
+        let stack_effects = disassembler.infer_stack_effects().unwrap();
+        let function = &disassembler.functions()[0];
+
+        let mut manager = StateManager::new(
+            function,
+            disassembler.functions(),
+            &stack_effects,
+            CallMode::Opaque,
+        );
+        manager.explore().unwrap();
+        let states = manager.returned_states();
+        let symbol = states[0].stack().elements().last().unwrap().clone();
+        let resimplified = simplify(symbol.clone());
+
+        for arg0 in VALUES {
+            for arg1 in VALUES {
+                let mut args = [0.0; NUM_ARGS];
+                args[0] = arg0;
+                args[1] = arg1;
+                let mut emulator = Emulator::new(disassembler.functions());
+                emulator.run("t", &args).unwrap();
+                let expected = *emulator.stack().last().unwrap();
+
+                let values =
+                    HashMap::from([("param_0".to_string(), arg0), ("param_1".to_string(), arg1)]);
+                for actual in [&symbol, &resimplified] {
+                    let value = actual.evaluate(&values).unwrap();
+                    assert!(
+                        same(value, expected),
+                        "{rewrite}: {body} as {actual} on ({arg0}, {arg1}) gives {value}, \
+                         not {expected}"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Returns `-argument`, without simplifying it.
+    fn negate(argument: Symbol) -> Symbol {
+        Symbol::from(UnaryExpressionSymbol::new(UnaryOperator::Negate, argument))
+    }
+
+    #[test]
+    fn double_negation_is_removed() {
+        let param = Symbol::from(IdentifierSymbol::new("param_0".to_string()));
+        assert_eq!(simplify(negate(negate(param.clone()))), param);
+        assert_eq!(
+            simplify(negate(negate(negate(param.clone())))),
+            negate(param.clone())
+        );
+        assert_eq!(simplify(negate(param.clone())), negate(param));
+    }
+}
@@ -1,6 +1,6 @@
 use std::fmt::Display;
 
-use super::symbol::Symbol;
+use super::{printer::Printer, symbol::Symbol};
 
 #[derive(Debug)]
 pub struct Stack {
@@ -28,6 +28,22 @@ impl Stack {
         self.size += 1;
     }
 
+    /// Returns the element at the given depth, where depth 0 is the top of
+    /// the stack.
+    pub fn get(&self, depth: usize) -> Option<&Symbol> {
+        if depth < self.size {
+            self.elements.get(self.size - depth - 1)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the elements of the stack, with the top of the stack as the
+    /// last element.
+    pub fn elements(&self) -> &Vec<Symbol> {
+        &self.elements
+    }
+
     /// Pops from the stack.
     pub fn pop(&mut self) -> Option<Symbol> {
         if let Some(elem) = self.elements.pop() {
@@ -39,18 +55,22 @@ impl Stack {
     }
 }
 
+impl Default for Stack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Display for Stack {
-    /// Writes a readable version of the stack.
+    /// Writes a readable version of the stack. Expressions shared between
+    /// elements are bound to temporaries and written first.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "[")?;
-        for (index, element) in self.elements.iter().enumerate() {
-            let separator = if index < self.elements.len() - 1 {
-                ", "
-            } else {
-                ""
-            };
-            write!(f, "{}{}", element, separator)?;
+        let mut printer = Printer::new();
+        printer.set_bind_shared(true);
+        let (bindings, elements) = printer.print_all(&self.elements);
+        for binding in bindings {
+            writeln!(f, "{}", binding)?;
         }
-        write!(f, "]")
+        write!(f, "[{}]", elements.join(", "))
     }
 }
@@ -1,15 +1,18 @@
 use crate::{
     disassembler::{
+        function::Function,
         instruction::{Instruction, Mnemonic},
-        operand::Operand,
+        operand::{Operand, Variable},
+        stack_effect::StackEffect,
     },
+    emulator::dialect::VmDialect,
     symbolic::symbol::{
-        BinaryExpressionSymbol, BinaryOperator, CallExpressionSymbol, IdentifierSymbol,
-        LiteralSymbol, UnaryExpressionSymbol, UnaryOperator,
+        BinaryExpressionSymbol, BinaryOperator, CallExpressionSymbol, CallResultSymbol,
+        IdentifierSymbol, LiteralSymbol, UnaryExpressionSymbol, UnaryOperator,
     },
 };
 
-use super::{stack::Stack, symbol::Symbol};
+use super::{simplifier::simplify_root, stack::Stack, summary::Summary, symbol::Symbol};
 use std::{collections::HashMap, error::Error};
 
 #[derive(Debug)]
@@ -19,35 +22,131 @@ pub enum Status {
     Errorred,
 }
 
+/// Maximum number of nested frames when calls are inlined.
+const MAX_CALL_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, Copy)]
+pub enum CallMode<'a> {
+    /// Calls produce opaque call expressions.
+    Opaque,
+    /// Calls descend into the callee, which runs against the caller's stack.
+    Inline,
+    /// Calls apply the callee's precomputed summary to the caller's stack.
+    Summary(&'a HashMap<String, Summary>),
+}
+
+/// A store whose nested block is being executed.
+#[derive(Debug)]
+struct PendingStore {
+    variable: String,
+    index: usize,
+    pos: usize,
+    length: usize,
+}
+
+#[derive(Debug)]
+struct Frame {
+    function_id: String,
+    pos: usize,
+    stores: Vec<PendingStore>,
+    arguments: Vec<Symbol>,
+}
+
+impl Frame {
+    /// Creates a new frame at the start of a function.
+    fn new(function_id: String, arguments: Vec<Symbol>) -> Frame {
+        Frame {
+            function_id,
+            pos: 0,
+            stores: Vec::new(),
+            arguments,
+        }
+    }
+
+    /// Returns the position within the innermost block being executed.
+    fn pos_mut(&mut self) -> &mut usize {
+        match self.stores.last_mut() {
+            Some(store) => &mut store.pos,
+            None => &mut self.pos,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct State {
     id: usize,
-    pos: usize,
+    frames: Vec<Frame>,
     variables: HashMap<String, Symbol>,
     stack: Stack,
     status: Status,
+    dialect: VmDialect,
 }
 
 impl State {
-    // Creates a new state with a given pos.
-    pub fn new(id: usize, pos: usize) -> State {
+    // Creates a new state at the start of a function.
+    pub fn new(id: usize, function_id: String) -> State {
         State {
             id,
-            pos,
+            frames: vec![Frame::new(function_id, Vec::new())],
             variables: HashMap::new(),
             stack: Stack::new(),
             status: Status::Active,
+            dialect: VmDialect::GCC,
         }
     }
 
+    /// Sets the dialect of the VM being executed, by default that of the
+    /// reference build.
+    pub fn set_dialect(&mut self, dialect: VmDialect) {
+        self.dialect = dialect;
+    }
+
     /// Returns the ID of the state.
     pub fn id(&self) -> usize {
         self.id
     }
 
-    /// Returns the position of the state.
+    /// Returns the position of the state within the current function.
     pub fn pos(&self) -> usize {
-        self.pos
+        self.frame().pos
+    }
+
+    /// Returns the next instruction to execute within the current function,
+    /// descending into the blocks of any stores being executed.
+    pub fn instruction<'f>(&self, function: &'f Function) -> Option<&'f Instruction> {
+        let frame = self.frame();
+        let mut block = function.instructions();
+        let mut pos = frame.pos;
+        for store in &frame.stores {
+            block = block.get(store.index)?.block();
+            pos = store.pos;
+        }
+        block.get(pos)
+    }
+
+    /// Returns the ID of the function currently being executed.
+    pub fn function_id(&self) -> &str {
+        &self.frame().function_id
+    }
+
+    /// Returns the innermost frame.
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("State has no frames")
+    }
+
+    /// Returns the innermost frame mutably.
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("State has no frames")
+    }
+
+    /// Returns the global variables assigned by the state.
+    pub fn variables(&self) -> &HashMap<String, Symbol> {
+        &self.variables
+    }
+
+    /// Returns the stack of the state.
+    pub fn stack(&self) -> &Stack {
+        &self.stack
     }
 
     /// Returns the status of the state.
@@ -56,11 +155,33 @@ impl State {
     }
 
     /// Pushes parameters to the stack before the symbolic execution starts.
+    /// A called function receives copies of the top stack slots as its
+    /// arguments, so param_0 ends up on top of the stack.
     pub fn push_params(&mut self, num_params: usize) {
-        for i in 0..num_params {
-            let param = IdentifierSymbol::new(format!("param_{i}"));
-            self.stack.push(Symbol::IdentifierSymbol(param));
+        let params: Vec<Symbol> = (0..num_params)
+            .map(|i| Symbol::from(IdentifierSymbol::new(format!("param_{i}"))))
+            .collect();
+        self.push_arguments(params);
+    }
+
+    /// Pushes symbols to the stack so that the first symbol is on top, and
+    /// uses them as the arguments of the current function.
+    pub fn push_arguments(&mut self, arguments: Vec<Symbol>) {
+        for argument in arguments.iter().rev() {
+            self.stack.push(argument.clone());
         }
+        self.frame_mut().arguments = arguments;
+    }
+
+    /// Copies the top 8 stack slots, which the VM passes as the arguments
+    /// to a call. Slots past the bottom of the stack are uninitialised.
+    fn copy_arguments(&self, count: usize) -> Vec<Symbol> {
+        (0..count)
+            .map(|depth| match self.stack.get(depth) {
+                Some(symbol) => symbol.clone(),
+                None => Symbol::from(IdentifierSymbol::new("undefined".to_string())),
+            })
+            .collect()
     }
 
     /// Helper method to access the ith operand from an instruction.
@@ -84,131 +205,419 @@ impl State {
             .ok_or("Got empty stack when operand expected".into())
     }
 
-    /// Steps the state by execution a single instruction.
-    pub fn step(&mut self, instruction: &Instruction) {
-        match self.execute(instruction) {
-            Ok(_) => {}
-            Err(e) => {
-                println!("{e}");
-                self.status = Status::Errorred
+    /// Pops the two operands of a binary opcode, returning the left and
+    /// right operands.
+    fn pop_operands(&mut self) -> Result<(Symbol, Symbol), Box<dyn Error>> {
+        let first = self.pop()?;
+        let second = self.pop()?;
+        Ok(self.dialect.operands(first, second))
+    }
+
+    /// Steps the state by executing a single instruction. If the instruction
+    /// cannot be executed, the state is marked as errored and the error is
+    /// returned.
+    pub fn step(
+        &mut self,
+        instruction: &Instruction,
+        stack_effects: &HashMap<String, StackEffect>,
+        call_mode: CallMode,
+    ) -> Result<(), Box<dyn Error>> {
+        let result = self
+            .execute(instruction, stack_effects, call_mode)
+            .and_then(|_| self.finish_stores());
+        if result.is_err() {
+            self.status = Status::Errorred;
+        }
+        result
+    }
+
+    /// Stores the results of any blocks in the current frame which have run
+    /// to completion.
+    fn finish_stores(&mut self) -> Result<(), Box<dyn Error>> {
+        while let Some(store) = self.frame().stores.last() {
+            if store.pos < store.length {
+                break;
             }
+            let variable = store.variable.clone();
+            self.frame_mut().stores.pop();
+            let value = self.pop()?;
+            self.variables.insert(variable, value);
         }
+        Ok(())
     }
 
     /// Symbolically executes an instruction.
-    fn execute(&mut self, instruction: &Instruction) -> Result<(), Box<dyn Error>> {
-        self.pos += 1;
+    fn execute(
+        &mut self,
+        instruction: &Instruction,
+        stack_effects: &HashMap<String, StackEffect>,
+        call_mode: CallMode,
+    ) -> Result<(), Box<dyn Error>> {
+        let frame = self.frame_mut();
+        let index = *frame.pos_mut();
+        *frame.pos_mut() += 1;
 
         match instruction.mnemonic() {
             Mnemonic::PUSH => {
                 let operand = self.get_operand(instruction, 0)?;
-                let symbol = Symbol::from(operand);
+                let symbol = match operand {
+                    Operand::Parameter(param) => match self.frame().arguments.get(param.index()) {
+                        Some(argument) => argument.clone(),
+                        None => Symbol::from(operand),
+                    },
+                    Operand::GlobalVariable(var) => match self.variables.get(var.name()) {
+                        Some(value) => value.clone(),
+                        None => Symbol::from(operand),
+                    },
+                    _ => Symbol::from(operand),
+                };
                 self.stack.push(symbol);
             }
             Mnemonic::POP => {
                 self.pop()?;
             }
             Mnemonic::ADD => {
-                let left = self.pop()?;
-                let right = self.pop()?;
-                // TODO: simplify logic for literals
-                let sum = BinaryExpressionSymbol::new(
-                    BinaryOperator::Add,
-                    Box::new(left),
-                    Box::new(right),
-                );
-                self.stack.push(Symbol::BinaryExpressionSymbol(sum));
+                let (left, right) = self.pop_operands()?;
+                let sum = BinaryExpressionSymbol::new(BinaryOperator::Add, left, right);
+                self.stack.push(simplify_root(Symbol::from(sum)));
             }
             Mnemonic::SUB => {
-                let left = self.pop()?;
-                let right = self.pop()?;
-                // TODO: simplify logic for literals
-                let sum = BinaryExpressionSymbol::new(
-                    BinaryOperator::Subtract,
-                    Box::new(left),
-                    Box::new(right),
-                );
-                self.stack.push(Symbol::BinaryExpressionSymbol(sum));
+                let (left, right) = self.pop_operands()?;
+                let sum = BinaryExpressionSymbol::new(BinaryOperator::Subtract, left, right);
+                self.stack.push(simplify_root(Symbol::from(sum)));
             }
             Mnemonic::MUL => {
-                let left = self.pop()?;
-                let right = self.pop()?;
-                // TODO: simplify logic for literals
-                let product = BinaryExpressionSymbol::new(
-                    BinaryOperator::Multiply,
-                    Box::new(left),
-                    Box::new(right),
-                );
-                self.stack.push(Symbol::BinaryExpressionSymbol(product));
+                let (left, right) = self.pop_operands()?;
+                let product = BinaryExpressionSymbol::new(BinaryOperator::Multiply, left, right);
+                self.stack.push(simplify_root(Symbol::from(product)));
             }
             Mnemonic::DIV => {
-                let left = self.pop()?;
-                let right = self.pop()?;
-                // TODO: simplify logic for literals
-                let div = BinaryExpressionSymbol::new(
-                    BinaryOperator::Divide,
-                    Box::new(left),
-                    Box::new(right),
-                );
-                self.stack.push(Symbol::BinaryExpressionSymbol(div));
+                let (left, right) = self.pop_operands()?;
+                let div = BinaryExpressionSymbol::new(BinaryOperator::Divide, left, right);
+                self.stack.push(simplify_root(Symbol::from(div)));
             }
-            // TODO: handle min and max
             Mnemonic::FRAC => {
-                let one = Symbol::LiteralSymbol(LiteralSymbol::One);
+                let one = Symbol::from(LiteralSymbol::One);
                 let operand = self.pop()?;
-                // TODO: simplify logic for literals
-                let frac = BinaryExpressionSymbol::new(
-                    BinaryOperator::Divide,
-                    Box::new(one),
-                    Box::new(operand),
-                );
-                self.stack.push(Symbol::BinaryExpressionSymbol(frac));
+                let frac = BinaryExpressionSymbol::new(BinaryOperator::Divide, one, operand);
+                self.stack.push(simplify_root(Symbol::from(frac)));
             }
             Mnemonic::NEG => {
                 let argument = self.pop()?;
-                // TODO: simplify logic for literals
-                let result = UnaryExpressionSymbol::new(UnaryOperator::Not, Box::new(argument));
-                self.stack.push(Symbol::UnaryExpressionSymbol(result));
+                let result = UnaryExpressionSymbol::new(UnaryOperator::Negate, argument);
+                self.stack.push(simplify_root(Symbol::from(result)));
             }
             Mnemonic::MIN | Mnemonic::MAX => {
-                let left = self.pop()?;
-                let right = self.pop()?;
+                let (left, right) = self.pop_operands()?;
                 let arguments = vec![left, right];
 
                 let callee_name = instruction.mnemonic().to_string();
                 let callee = IdentifierSymbol::new(callee_name);
 
-                let call = CallExpressionSymbol::new(
-                    Box::new(Symbol::IdentifierSymbol(callee)),
-                    arguments,
-                );
-                self.stack.push(Symbol::CallExpressionSymbol(call))
+                let call = CallExpressionSymbol::new(Symbol::from(callee), arguments);
+                self.stack.push(simplify_root(Symbol::from(call)))
             }
             Mnemonic::CALL => {
                 let operand = self.get_operand(instruction, 0)?;
-                let callee = Symbol::from(operand);
+                let (callee_id, effect) = match operand {
+                    Operand::FunctionReference(func_ref) => (
+                        func_ref.id(),
+                        stack_effects
+                            .get(func_ref.id())
+                            .ok_or(format!("Unknown stack effect for {}", func_ref.to_string()))?,
+                    ),
+                    _ => return Err("Expected function reference for call".into()),
+                };
+
+                match call_mode {
+                    CallMode::Opaque => {
+                        let callee = Symbol::from(operand);
 
-                let mut arguments = Vec::new();
-                let mut i = 0;
-                while i < 8 && self.stack.size() > 0 {
-                    if let Some(operand) = self.stack.pop() {
-                        arguments.push(operand);
+                        // the VM copies the top 8 stack slots into the arguments
+                        // without popping them. Only the arguments the callee can
+                        // observe are kept
+                        let arguments = self.copy_arguments(effect.arguments());
+
+                        // the callee then runs on the shared stack, popping some of
+                        // the caller's slots and leaving its results in their place
+                        for _ in 0..effect.consumed() {
+                            self.pop()?;
+                        }
+                        let call = Symbol::from(CallExpressionSymbol::new(callee, arguments));
+                        if effect.produced() == 1 {
+                            self.stack.push(call);
+                        } else {
+                            for index in 0..effect.produced() {
+                                let result = CallResultSymbol::new(call.clone(), index);
+                                self.stack.push(Symbol::from(result));
+                            }
+                        }
+                    }
+                    CallMode::Inline => {
+                        if self.frames.len() >= MAX_CALL_DEPTH {
+                            return Err(
+                                format!("Exceeded max call depth of {MAX_CALL_DEPTH}").into()
+                            );
+                        }
+
+                        // the callee runs against the caller's stack, its results
+                        // are left on the stack when it returns
+                        let arguments = self.copy_arguments(8);
+                        self.frames
+                            .push(Frame::new(callee_id.to_string(), arguments));
+                    }
+                    CallMode::Summary(summaries) => {
+                        let summary = summaries
+                            .get(callee_id)
+                            .ok_or(format!("No summary for func_{callee_id}"))?;
+
+                        // the summary is applied to the slots the callee can see,
+                        // which are replaced by its results
+                        let slots = self.copy_arguments(summary.slots());
+                        let results = summary.apply(&slots, &self.variables);
+                        let variables = summary.apply_variables(&slots, &self.variables);
+                        for _ in 0..summary.consumed() {
+                            self.pop()?;
+                        }
+                        for result in results {
+                            self.stack.push(result);
+                        }
+                        self.variables.extend(variables);
                     }
-                    i += 1;
                 }
+            }
+            Mnemonic::STORE => {
+                let variable = match self.get_operand(instruction, 0)? {
+                    Operand::GlobalVariable(var) => var.name().to_string(),
+                    _ => return Err("Expected global variable for store".into()),
+                };
 
-                let call = CallExpressionSymbol::new(Box::new(callee), arguments);
-                println!("Call expression:");
-                println!("{}\n", call);
-                self.stack.push(Symbol::CallExpressionSymbol(call))
+                // the block runs next, its result is stored once it finishes
+                self.frame_mut().stores.push(PendingStore {
+                    variable,
+                    index,
+                    pos: 0,
+                    length: instruction.block().len(),
+                });
             }
-            Mnemonic::RET => {
-                println!("Stack on function return:");
-                println!("{}\n\n\n\n", self.stack);
-                self.status = Status::Terminated;
+            Mnemonic::UNKNOWN => {}
+            Mnemonic::INVALID => return Err("Cannot symbolically execute invalid bytecode".into()),
+            Mnemonic::RET => self.return_from_function()?,
+        }
+
+        Ok(())
+    }
+
+    /// Returns from the current function, as its `ret` does or as it does
+    /// when it runs out of instructions. Returning from an inlined callee
+    /// resumes the caller, completing any store whose block ended with the
+    /// call. Otherwise the state terminates.
+    pub fn return_from_function(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.frames.len() > 1 {
+            self.frames.pop();
+            let result = self.finish_stores();
+            if result.is_err() {
+                self.status = Status::Errorred;
             }
+            return result;
         }
 
+        self.status = Status::Terminated;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        fs,
+    };
+
+    use super::CallMode;
+    use crate::{
+        disassembler::disassembler::Disassembler,
+        symbolic::{
+            state_manager::StateManager,
+            symbol::{Symbol, SymbolKind},
+        },
+    };
+
+    /// Symbolically executes a function of the given bytecode, returning the
+    /// stack it returns with.
+    fn returned_stack(bytecode: &str, id: &str, call_mode: CallMode) -> Vec<Symbol> {
+        returned(bytecode, id, call_mode).0
+    }
+
+    /// Symbolically executes a function of the given bytecode, returning the
+    /// stack it returns with and the variables it assigns, sorted by name.
+    fn returned(bytecode: &str, id: &str, call_mode: CallMode) -> (Vec<Symbol>, Vec<String>) {
+        let mut disassembler = Disassembler::new(bytecode.to_string());
+        disassembler.disassemble().unwrap();
+        let stack_effects = disassembler.infer_stack_effects().unwrap();
+        let function = disassembler
+            .functions()
+            .iter()
+            .find(|func| func.id() == id)
+            .unwrap();
+
+        let mut manager = StateManager::new(
+            function,
+            disassembler.functions(),
+            &stack_effects,
+            call_mode,
+        );
+        manager.explore().unwrap();
+        let states = manager.returned_states();
+        assert_eq!(states.len(), 1);
+        let mut variables: Vec<String> = states[0]
+            .variables()
+            .iter()
+            .map(|(name, value)| format!("{name} = {value}"))
+            .collect();
+        variables.sort();
+        (states[0].stack().elements().clone(), variables)
+    }
+
+    /// Symbolically executes function `t` with opaque calls, returning the
+    /// stack it returns with.
+    fn opaque_stack(bytecode: &str) -> Vec<String> {
+        returned_stack(bytecode, "t", CallMode::Opaque)
+            .iter()
+            .map(|symbol| symbol.to_string())
+            .collect()
+    }
+
+    /// Returns whether any of the symbols calls a function, rather than
+    /// `min` or `max`.
+    fn calls_function(symbols: &[Symbol]) -> bool {
+        let mut visited = HashSet::new();
+        let mut worklist = symbols.to_vec();
+        while let Some(symbol) = worklist.pop() {
+            if !visited.insert(symbol.clone()) {
+                continue;
+            }
+            match symbol.kind() {
+                SymbolKind::CallExpressionSymbol(call)
+                    if !matches!(call.callee().to_string().as_str(), "min" | "max") =>
+                {
+                    return true
+                }
+                SymbolKind::CallResultSymbol(_) => return true,
+                _ => worklist.extend(symbol.children()),
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn stored_value_is_read_back() {
+        let (stack, variables) = returned(":t:a=23-;b=a!;ab;", "t", CallMode::Opaque);
+        let stack: Vec<String> = stack.iter().map(Symbol::to_string).collect();
+        // the parameters t is called with stay below its results
+        assert_eq!(
+            stack,
+            [
+                "param_1",
+                "param_0",
+                "param_1 - param_0",
+                "-(param_1 - param_0)"
+            ]
+        );
+        assert_eq!(
+            variables,
+            [
+                "global_a = param_1 - param_0",
+                "global_b = -(param_1 - param_0)"
+            ]
+        );
+    }
+
+    #[test]
+    fn store_ending_in_call_completes_after_return() {
+        // the block's result is the callee's, so the store only completes
+        // once the inlined callee's `ret` has popped its frame
+        for bytecode in [":f:2!;:t:a=01^f;a;", ":t:a=01^f;a;:f:2!"] {
+            let (stack, variables) = returned(bytecode, "t", CallMode::Inline);
+            let stack: Vec<String> = stack.iter().map(Symbol::to_string).collect();
+            assert_eq!(stack, ["0.0", "Infinity", "-Infinity"], "{bytecode}");
+            assert_eq!(variables, ["global_a = -Infinity"], "{bytecode}");
+        }
+
+        let (stack, variables) = returned(":f:2!;:t:a=01^f;a;", "t", CallMode::Opaque);
+        let stack: Vec<String> = stack.iter().map(Symbol::to_string).collect();
+        assert_eq!(stack, ["0.0", "Infinity", "func_f(Infinity)"]);
+        assert_eq!(variables, ["global_a = func_f(Infinity)"]);
+    }
+
+    #[test]
+    fn running_off_the_end_returns() {
+        assert_eq!(
+            returned_stack(":f:01", "f", CallMode::Opaque)
+                .iter()
+                .map(Symbol::to_string)
+                .collect::<Vec<String>>(),
+            ["0.0", "Infinity"]
+        );
+
+        // an inlined callee without a `ret` resumes its caller
+        let inlined: Vec<String> = returned_stack(":t:^f1;:f:0", "t", CallMode::Inline)
+            .iter()
+            .map(Symbol::to_string)
+            .collect();
+        assert_eq!(inlined, ["0.0", "Infinity"]);
+    }
+
+    #[test]
+    fn negation_flips_sign_of_zero() {
+        let stack = returned_stack(":t:0!1!;", "t", CallMode::Opaque);
+        let values: Vec<u32> = stack
+            .iter()
+            .map(|symbol| symbol.evaluate(&HashMap::new()).unwrap().to_bits())
+            .collect();
+        assert_eq!(values, [(-0.0f32).to_bits(), f32::NEG_INFINITY.to_bits()]);
+        assert_eq!(opaque_stack(":t:0!1!;"), ["-0.0", "-Infinity"]);
+    }
+
+    #[test]
+    fn call_copies_arguments_without_popping() {
+        // h reads its first argument without popping anything
+        assert_eq!(
+            opaque_stack(":h:2;:t:01^h;"),
+            ["0.0", "Infinity", "func_h(Infinity)"]
+        );
+    }
+
+    #[test]
+    fn call_replaces_consumed_slots_with_its_result() {
+        // f pops the two slots it adds, the slot below them is kept
+        assert_eq!(
+            opaque_stack(":f:..23+;:t:001^f;"),
+            ["0.0", "func_f(Infinity, 0.0)"]
+        );
+    }
+
+    #[test]
+    fn call_with_several_results_pushes_each_in_order() {
+        assert_eq!(
+            opaque_stack(":g:23;:t:01^g;"),
+            [
+                "0.0",
+                "Infinity",
+                "func_g(Infinity, 0.0)[0]",
+                "func_g(Infinity, 0.0)[1]"
+            ]
+        );
+    }
+
+    #[test]
+    fn inlining_gives_closed_form() {
+        let bytecode = fs::read_to_string("input/bytecode.txt").unwrap();
+        let opaque = returned_stack(&bytecode, "xb", CallMode::Opaque);
+        assert!(calls_function(&opaque));
+
+        let inlined = returned_stack(&bytecode, "xb", CallMode::Inline);
+        assert_eq!(inlined.len(), opaque.len());
+        assert!(!calls_function(&inlined));
+    }
+}
@@ -1,36 +1,73 @@
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
 
-use crate::disassembler::{function::Function, instruction::Instruction};
+use crate::{
+    disassembler::{function::Function, stack_effect::StackEffect},
+    emulator::dialect::VmDialect,
+};
 
-use super::state::{State, Status};
+use super::state::{CallMode, State, Status};
 
 #[derive(Debug)]
-pub struct StateManager {
-    instructions: Vec<Instruction>,
+pub struct StateManager<'a> {
+    functions: HashMap<&'a str, &'a Function>,
+    stack_effects: &'a HashMap<String, StackEffect>,
+    call_mode: CallMode<'a>,
     states: Vec<State>,
     active_state_ids: HashSet<usize>,
     terminated_state_ids: HashSet<usize>,
 }
 
-impl StateManager {
-    /// Creates a new state manager.
-    pub fn new(function: Function) -> StateManager {
-        let mut entry_state = State::new(0, 0);
+impl<'a> StateManager<'a> {
+    /// Creates a new state manager for exploring a function. The other
+    /// functions and their stack effects are used to model calls.
+    pub fn new(
+        function: &'a Function,
+        functions: &'a [Function],
+        stack_effects: &'a HashMap<String, StackEffect>,
+        call_mode: CallMode<'a>,
+    ) -> StateManager<'a> {
+        let mut entry_state = State::new(0, function.id().to_string());
         entry_state.push_params(function.num_params());
 
         let mut active_state_ids = HashSet::new();
         active_state_ids.insert(entry_state.id());
 
         StateManager {
-            instructions: function.instructions(),
+            functions: functions.iter().map(|func| (func.id(), func)).collect(),
+            stack_effects,
+            call_mode,
             states: vec![entry_state],
             active_state_ids,
             terminated_state_ids: HashSet::new(),
         }
     }
 
-    /// Keeps simulating until all states have terminated.
-    pub fn explore(&mut self) {
+    /// Sets the dialect of the VM being executed, by default that of the
+    /// reference build.
+    pub fn set_dialect(&mut self, dialect: VmDialect) {
+        for state in &mut self.states {
+            state.set_dialect(dialect);
+        }
+    }
+
+    /// Returns the states which returned from the function, in the order
+    /// they were created.
+    pub fn returned_states(&self) -> Vec<&State> {
+        self.states
+            .iter()
+            .filter(|state| {
+                self.terminated_state_ids.contains(&state.id())
+                    && matches!(state.status(), Status::Terminated)
+            })
+            .collect()
+    }
+
+    /// Keeps simulating until all states have terminated. Returns the error
+    /// of the first state which could not be executed, if any.
+    pub fn explore(&mut self) -> Result<(), Box<dyn Error>> {
         loop {
             let mut ids_to_remove = Vec::new();
 
@@ -39,16 +76,26 @@ impl StateManager {
                     .states
                     .get_mut(*id)
                     .expect("Unexpected error when exploring states");
-                if let Some(instruction) = self.instructions.get(state.pos()) {
-                    state.step(instruction);
+                let instruction = self
+                    .functions
+                    .get(state.function_id())
+                    .and_then(|func| state.instruction(func));
+                match instruction {
+                    Some(instruction) => {
+                        state.step(instruction, self.stack_effects, self.call_mode)?
+                    }
+                    // running off the end of a function without a `ret`
+                    // returns from it, as in the emulator
+                    None => state.return_from_function()?,
+                }
 
-                    match *state.status() {
-                        Status::Active => (),
-                        _ => ids_to_remove.push(*id),
+                match *state.status() {
+                    Status::Active => (),
+                    Status::Terminated => {
+                        ids_to_remove.push(*id);
+                        self.terminated_state_ids.insert(*id);
                     }
-                } else {
-                    ids_to_remove.push(*id);
-                    self.terminated_state_ids.insert(*id);
+                    Status::Errorred => ids_to_remove.push(*id),
                 }
             }
 
@@ -56,8 +103,8 @@ impl StateManager {
                 self.active_state_ids.remove(&id);
             }
 
-            if self.active_state_ids.len() == 0 {
-                break;
+            if self.active_state_ids.is_empty() {
+                return Ok(());
             }
         }
     }
@@ -0,0 +1,393 @@
+use std::{collections::HashMap, error::Error};
+
+use crate::{
+    disassembler::{
+        function::Function, instruction::Mnemonic, operand::Operand, stack_effect::StackEffect,
+    },
+    emulator::dialect::VmDialect,
+};
+
+use super::{
+    simplifier::simplify,
+    state::{CallMode, State, Status},
+    symbol::{IdentifierSymbol, Symbol},
+};
+
+/// A function's effect on the stack, expressed in terms of placeholders for
+/// the stack slots it can see when it is called.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    slots: usize,
+    consumed: usize,
+    results: Vec<Symbol>,
+    variables: HashMap<String, Symbol>,
+}
+
+impl Summary {
+    /// Returns the number of stack slots the summary is parameterised over,
+    /// starting from the top of the stack.
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    /// Returns the number of stack slots the function pops.
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// Returns the symbols the function leaves on the stack, with the top of
+    /// the stack last.
+    pub fn results(&self) -> &Vec<Symbol> {
+        &self.results
+    }
+
+    /// Returns the global variables the function assigns, keyed by name.
+    pub fn variables(&self) -> &HashMap<String, Symbol> {
+        &self.variables
+    }
+
+    /// Applies the summary to the stack slots at a call site, given with the
+    /// top of the stack first, and the variables assigned by the caller.
+    /// Returns the results to push in their place.
+    pub fn apply(&self, slots: &[Symbol], variables: &HashMap<String, Symbol>) -> Vec<Symbol> {
+        let replacements = replacements(slots, variables);
+        self.results
+            .iter()
+            .map(|result| simplify(result.substitute(&replacements)))
+            .collect()
+    }
+
+    /// Applies the variables the function assigns to a call site, in the
+    /// same way as `apply`. Returns the values to assign in the caller.
+    pub fn apply_variables(
+        &self,
+        slots: &[Symbol],
+        variables: &HashMap<String, Symbol>,
+    ) -> HashMap<String, Symbol> {
+        let replacements = replacements(slots, variables);
+        self.variables
+            .iter()
+            .map(|(name, value)| (name.clone(), simplify(value.substitute(&replacements))))
+            .collect()
+    }
+}
+
+/// Returns the replacements for the placeholders of the stack slots and
+/// the variables the caller has assigned.
+fn replacements(slots: &[Symbol], variables: &HashMap<String, Symbol>) -> HashMap<Symbol, Symbol> {
+    let slots = slots.iter().enumerate().map(|(index, slot)| {
+        (
+            Symbol::from(IdentifierSymbol::new(slot_name(index))),
+            slot.clone(),
+        )
+    });
+    let variables = variables.iter().map(|(name, value)| {
+        (
+            Symbol::from(IdentifierSymbol::new(name.clone())),
+            value.clone(),
+        )
+    });
+    slots.chain(variables).collect()
+}
+
+/// Returns the name of the placeholder for a stack slot.
+fn slot_name(index: usize) -> String {
+    format!("slot_{index}")
+}
+
+/// Summaries of the functions of a program, computed on demand. Each callee
+/// is summarised once, after the functions it calls, so that `CallMode::Summary`
+/// can apply a call without descending into the callee again.
+#[derive(Debug)]
+pub struct SummaryCache<'a> {
+    functions_by_id: HashMap<&'a str, &'a Function>,
+    stack_effects: &'a HashMap<String, StackEffect>,
+    summaries: HashMap<String, Summary>,
+    dialect: VmDialect,
+}
+
+impl<'a> SummaryCache<'a> {
+    /// Creates a new, empty summary cache.
+    pub fn new(
+        functions: &'a [Function],
+        stack_effects: &'a HashMap<String, StackEffect>,
+    ) -> SummaryCache<'a> {
+        SummaryCache {
+            functions_by_id: functions.iter().map(|func| (func.id(), func)).collect(),
+            stack_effects,
+            summaries: HashMap::new(),
+            dialect: VmDialect::GCC,
+        }
+    }
+
+    /// Sets the dialect of the VM being executed, by default that of the
+    /// reference build. Summaries computed under another dialect are
+    /// discarded.
+    pub fn set_dialect(&mut self, dialect: VmDialect) {
+        if dialect != self.dialect {
+            self.summaries.clear();
+        }
+        self.dialect = dialect;
+    }
+
+    /// Returns the summaries computed so far, keyed by function ID.
+    pub fn summaries(&self) -> &HashMap<String, Summary> {
+        &self.summaries
+    }
+
+    /// Summarises every function called by a function, if they have not
+    /// already been summarised.
+    pub fn summarise_callees(&mut self, function: &Function) -> Result<(), Box<dyn Error>> {
+        for callee in get_callees(function, &self.functions_by_id)? {
+            self.summarise(callee)?;
+        }
+        Ok(())
+    }
+
+    /// Computes the summary of a function, first computing the summaries of
+    /// any functions it calls.
+    fn summarise(&mut self, function: &Function) -> Result<(), Box<dyn Error>> {
+        if self.summaries.contains_key(function.id()) {
+            return Ok(());
+        }
+        self.summarise_callees(function)?;
+
+        let effect = self
+            .stack_effects
+            .get(function.id())
+            .ok_or(format!("Unknown stack effect for func_{}", function.id()))?;
+
+        // the function can see every slot it pops, as well as the 8 slots
+        // copied into its arguments
+        let slots = effect.consumed().max(8);
+        let placeholders = (0..slots)
+            .map(|index| Symbol::from(IdentifierSymbol::new(slot_name(index))))
+            .collect();
+
+        let mut state = State::new(0, function.id().to_string());
+        state.set_dialect(self.dialect);
+        state.push_arguments(placeholders);
+
+        while let Some(instruction) = state.instruction(function) {
+            state
+                .step(
+                    instruction,
+                    self.stack_effects,
+                    CallMode::Summary(&self.summaries),
+                )
+                .map_err(|e| format!("Failed to summarise func_{}: {e}", function.id()))?;
+            if let Status::Terminated = state.status() {
+                break;
+            }
+        }
+
+        let results = (0..effect.produced())
+            .rev()
+            .map(|depth| state.stack().get(depth).cloned())
+            .collect::<Option<Vec<Symbol>>>()
+            .ok_or(format!(
+                "Stack of func_{} is smaller than its stack effect",
+                function.id()
+            ))?;
+
+        self.summaries.insert(
+            function.id().to_string(),
+            Summary {
+                slots,
+                consumed: effect.consumed(),
+                results,
+                variables: state.variables().clone(),
+            },
+        );
+        Ok(())
+    }
+}
+
+/// Returns the functions called by a function.
+fn get_callees<'a>(
+    function: &Function,
+    functions_by_id: &HashMap<&str, &'a Function>,
+) -> Result<Vec<&'a Function>, Box<dyn Error>> {
+    let mut callees = Vec::new();
+    for instr in function.all_instructions() {
+        if let Mnemonic::CALL = instr.mnemonic() {
+            if let Some(Operand::FunctionReference(func_ref)) = instr.operands().first() {
+                let callee = functions_by_id
+                    .get(func_ref.id())
+                    .ok_or(format!("Unknown callee {}", func_ref.to_string()))?;
+                callees.push(*callee);
+            }
+        }
+    }
+    Ok(callees)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{Summary, SummaryCache};
+    use crate::{
+        disassembler::disassembler::Disassembler,
+        symbolic::{
+            state::CallMode,
+            state_manager::StateManager,
+            symbol::{IdentifierSymbol, Symbol},
+        },
+    };
+
+    /// Disassembles some bytecode.
+    fn disassemble(bytecode: &str) -> Disassembler {
+        let mut disassembler = Disassembler::new(bytecode.to_string());
+        disassembler.disassemble().unwrap();
+        disassembler
+    }
+
+    /// Returns an identifier symbol.
+    fn ident(name: &str) -> Symbol {
+        Symbol::from(IdentifierSymbol::new(name.to_string()))
+    }
+
+    /// Returns the summary of function `f`, as called by function `t`.
+    fn summary_of(bytecode: &str) -> Summary {
+        let mut disassembler = disassemble(bytecode);
+        let stack_effects = disassembler.infer_stack_effects().unwrap();
+        let mut cache = SummaryCache::new(disassembler.functions(), &stack_effects);
+        cache
+            .summarise_callees(disassembler.functions().last().unwrap())
+            .unwrap();
+        cache.summaries()["f"].clone()
+    }
+
+    /// Summarises the callees of function `t`, returning the results of each
+    /// summary by function ID.
+    fn summarise(bytecode: &str) -> HashMap<String, Vec<String>> {
+        let mut disassembler = disassemble(bytecode);
+        let stack_effects = disassembler.infer_stack_effects().unwrap();
+        let mut cache = SummaryCache::new(disassembler.functions(), &stack_effects);
+        let t = disassembler.functions().last().unwrap();
+        cache.summarise_callees(t).unwrap();
+        cache
+            .summaries()
+            .iter()
+            .map(|(id, summary)| {
+                let results = summary.results().iter().map(Symbol::to_string).collect();
+                (id.clone(), results)
+            })
+            .collect()
+    }
+
+    /// Symbolically executes function `t` in the given call mode, returning
+    /// the stack and variables it returns with.
+    fn execute(bytecode: &str, summaries: bool) -> (Vec<String>, Vec<(String, String)>) {
+        let mut disassembler = disassemble(bytecode);
+        let stack_effects = disassembler.infer_stack_effects().unwrap();
+        let t = disassembler.functions().last().unwrap();
+        let mut cache = SummaryCache::new(disassembler.functions(), &stack_effects);
+        cache.summarise_callees(t).unwrap();
+        let call_mode = if summaries {
+            CallMode::Summary(cache.summaries())
+        } else {
+            CallMode::Inline
+        };
+
+        let mut manager = StateManager::new(t, disassembler.functions(), &stack_effects, call_mode);
+        manager.explore().unwrap();
+        let states = manager.returned_states();
+        assert_eq!(states.len(), 1);
+        let stack = states[0]
+            .stack()
+            .elements()
+            .iter()
+            .map(Symbol::to_string)
+            .collect();
+        let mut variables: Vec<(String, String)> = states[0]
+            .variables()
+            .iter()
+            .map(|(name, value)| (name.clone(), value.to_string()))
+            .collect();
+        variables.sort();
+        (stack, variables)
+    }
+
+    #[test]
+    fn placeholders_are_substituted_at_call_site() {
+        let summary = summary_of(":f:b=23-;b;;:t:^f;");
+        assert_eq!(summary.slots(), 8);
+        assert_eq!(summary.results()[0].to_string(), "slot_1 - slot_0");
+        assert_eq!(
+            summary.variables()["global_b"].to_string(),
+            "slot_1 - slot_0"
+        );
+
+        // the top of the stack at the call site is slot_0
+        let slots = [ident("x"), ident("y")];
+        assert_eq!(
+            summary.apply(&slots, &HashMap::new())[0].to_string(),
+            "y - x"
+        );
+        assert_eq!(
+            summary.apply_variables(&slots, &HashMap::new())["global_b"].to_string(),
+            "y - x"
+        );
+    }
+
+    #[test]
+    fn variables_read_before_assigned_are_placeholders() {
+        let summary = summary_of(":f:c2+;:t:^f;");
+        assert_eq!(summary.results()[0].to_string(), "slot_0 + global_c");
+
+        // the caller's value of the variable replaces it
+        let variables = HashMap::from([("global_c".to_string(), ident("z"))]);
+        assert_eq!(
+            summary.apply(&[ident("x")], &variables)[0].to_string(),
+            "x + z"
+        );
+        assert_eq!(
+            summary.apply(&[ident("x")], &HashMap::new())[0].to_string(),
+            "x + global_c"
+        );
+    }
+
+    #[test]
+    fn callee_sees_every_slot_it_pops() {
+        // f pops 11 slots, more than the 8 copied into its arguments
+        let bytecode = ":f:.........+;:t:00000000000^f;";
+        let summary = summary_of(bytecode);
+        assert_eq!(summary.consumed(), 11);
+        assert_eq!(summary.slots(), 11);
+        assert_eq!(summary.results()[0].to_string(), "slot_9 + slot_10");
+        assert_eq!(execute(bytecode, true), execute(bytecode, false));
+    }
+
+    #[test]
+    fn nested_callees_are_summarised_first() {
+        let summaries = summarise(":g:23*;:f:2^g;:t:01^f;");
+        assert_eq!(summaries["g"], ["slot_1 * slot_0"]);
+        // g is applied within f, rather than left as a call
+        assert_eq!(summaries["f"], ["slot_0", "slot_0 * slot_0"]);
+        assert!(!summaries.contains_key("t"));
+    }
+
+    #[test]
+    fn summaries_agree_with_inlining() {
+        for bytecode in [
+            ":f:a=23+;a3;:t:01^f;",
+            ":f:a=23+;a3;:t:45^f;",
+            ":g:23*;:f:2^g;:t:01^f;",
+            ":f:a=23-;;:g:a^f3;:t:a=0;1^g^fa;",
+        ] {
+            assert_eq!(
+                execute(bytecode, true),
+                execute(bytecode, false),
+                "{bytecode}"
+            );
+        }
+        let (stack, variables) = execute(":f:a=23+;a3;:t:01^f;", true);
+        assert_eq!(stack, ["0.0", "Infinity", "Infinity", "0.0"]);
+        assert_eq!(
+            variables,
+            [("global_a".to_string(), "Infinity".to_string())]
+        );
+    }
+}
@@ -1,35 +1,301 @@
-use std::fmt::Display;
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt::Display,
+    hash::{Hash, Hasher},
+    rc::{Rc, Weak},
+};
 
-use crate::disassembler::operand::{Literal, Operand, Variable};
+use super::printer::Printer;
+use crate::{
+    disassembler::operand::{Literal, Operand, Variable},
+    emulator::emulator::{fmax, fmin},
+};
 
-#[derive(Debug)]
-pub enum Symbol {
+thread_local! {
+    /// Every symbol alive on this thread, by the hash of what it is, so that
+    /// structurally equal symbols share a node.
+    static INTERNED: RefCell<HashMap<u64, Vec<Weak<Node>>>> = RefCell::new(HashMap::new());
+
+    /// ID of the next symbol created on this thread.
+    static NEXT_ID: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Node of the expression DAG, which is freed once no symbol refers to it.
+struct Node {
+    id: usize,
+    kind: SymbolKind,
+}
+
+impl Drop for Node {
+    /// Removes the node from the symbols which are alive.
+    fn drop(&mut self) {
+        let hash = hash_kind(&self.kind);
+        // nothing is left to remove once the thread is exiting
+        let _ = INTERNED.try_with(|interned| {
+            let Ok(mut interned) = interned.try_borrow_mut() else {
+                return;
+            };
+            if let Some(nodes) = interned.get_mut(&hash) {
+                nodes.retain(|node| node.strong_count() > 0);
+                if nodes.is_empty() {
+                    interned.remove(&hash);
+                }
+            }
+        });
+    }
+}
+
+/// Returns the hash of what a symbol is.
+fn hash_kind(kind: &SymbolKind) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    kind.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Handle to a hash-consed symbol. Structurally equal symbols which are
+/// alive at the same time share a node, so symbols are compared and hashed
+/// by their ID. IDs increase in order of creation, so a symbol's children
+/// always have smaller IDs.
+///
+/// Nodes are reference counted, and a symbol cannot be sent to another
+/// thread, as it is only interned with the symbols of its own thread.
+#[derive(Clone)]
+pub struct Symbol {
+    node: Rc<Node>,
+}
+
+impl Symbol {
+    /// Interns a symbol, sharing the node of an equal symbol if one is
+    /// alive.
+    pub fn new(kind: SymbolKind) -> Symbol {
+        let hash = hash_kind(&kind);
+        let existing = INTERNED.with(|interned| {
+            interned.borrow().get(&hash).and_then(|nodes| {
+                nodes
+                    .iter()
+                    .filter_map(Weak::upgrade)
+                    .find(|node| node.kind == kind)
+            })
+        });
+        if let Some(node) = existing {
+            return Symbol { node };
+        }
+
+        let id = NEXT_ID.with(|next_id| next_id.replace(next_id.get() + 1));
+        let node = Rc::new(Node { id, kind });
+        INTERNED.with(|interned| {
+            interned
+                .borrow_mut()
+                .entry(hash)
+                .or_default()
+                .push(Rc::downgrade(&node));
+        });
+        Symbol { node }
+    }
+
+    /// Returns the unique ID of the symbol.
+    pub fn id(&self) -> usize {
+        self.node.id
+    }
+
+    /// Returns what the symbol is.
+    pub fn kind(&self) -> &SymbolKind {
+        &self.node.kind
+    }
+
+    /// Returns the symbols directly referenced by the symbol.
+    pub fn children(&self) -> Vec<Symbol> {
+        match self.kind() {
+            SymbolKind::LiteralSymbol(_) | SymbolKind::IdentifierSymbol(_) => Vec::new(),
+            SymbolKind::UnaryExpressionSymbol(unary) => vec![unary.argument.clone()],
+            SymbolKind::BinaryExpressionSymbol(binary) => {
+                vec![binary.left.clone(), binary.right.clone()]
+            }
+            SymbolKind::CallExpressionSymbol(call) => {
+                let mut children = vec![call.callee.clone()];
+                children.extend(call.arguments.iter().cloned());
+                children
+            }
+            SymbolKind::CallResultSymbol(result) => vec![result.call.clone()],
+        }
+    }
+
+    /// Returns a copy of the symbol with symbols replaced by the symbols
+    /// they map to.
+    pub fn substitute(&self, replacements: &HashMap<Symbol, Symbol>) -> Symbol {
+        self.substitute_memoised(replacements, &mut HashMap::new())
+    }
+
+    /// Substitutes symbols, visiting each shared subexpression only once.
+    fn substitute_memoised(
+        &self,
+        replacements: &HashMap<Symbol, Symbol>,
+        memo: &mut HashMap<Symbol, Symbol>,
+    ) -> Symbol {
+        if let Some(replacement) = replacements.get(self) {
+            return replacement.clone();
+        }
+        if let Some(result) = memo.get(self) {
+            return result.clone();
+        }
+
+        let result = match self.kind() {
+            SymbolKind::LiteralSymbol(_) | SymbolKind::IdentifierSymbol(_) => self.clone(),
+            SymbolKind::UnaryExpressionSymbol(unary) => Symbol::from(UnaryExpressionSymbol::new(
+                unary.operator.clone(),
+                unary.argument.substitute_memoised(replacements, memo),
+            )),
+            SymbolKind::BinaryExpressionSymbol(binary) => {
+                Symbol::from(BinaryExpressionSymbol::new(
+                    binary.operator.clone(),
+                    binary.left.substitute_memoised(replacements, memo),
+                    binary.right.substitute_memoised(replacements, memo),
+                ))
+            }
+            SymbolKind::CallExpressionSymbol(call) => Symbol::from(CallExpressionSymbol::new(
+                call.callee.substitute_memoised(replacements, memo),
+                call.arguments
+                    .iter()
+                    .map(|arg| arg.substitute_memoised(replacements, memo))
+                    .collect(),
+            )),
+            SymbolKind::CallResultSymbol(result) => Symbol::from(CallResultSymbol::new(
+                result.call.substitute_memoised(replacements, memo),
+                result.index,
+            )),
+        };
+        memo.insert(self.clone(), result.clone());
+        result
+    }
+}
+
+impl Symbol {
+    /// Evaluates the symbol, given the values of its identifiers. Returns
+    /// `None` if an identifier has no value, or for calls other than to
+    /// `min` and `max`.
+    pub fn evaluate(&self, values: &HashMap<String, f32>) -> Option<f32> {
+        self.evaluate_memoised(values, &mut HashMap::new())
+    }
+
+    /// Evaluates the symbol, visiting each shared subexpression only once.
+    fn evaluate_memoised(
+        &self,
+        values: &HashMap<String, f32>,
+        memo: &mut HashMap<Symbol, f32>,
+    ) -> Option<f32> {
+        if let Some(value) = memo.get(self) {
+            return Some(*value);
+        }
+
+        let value = match self.kind() {
+            SymbolKind::LiteralSymbol(literal) => literal.value(),
+            SymbolKind::IdentifierSymbol(ident) => *values.get(ident.name())?,
+            SymbolKind::UnaryExpressionSymbol(unary) => unary
+                .operator
+                .apply(unary.argument.evaluate_memoised(values, memo)?),
+            SymbolKind::BinaryExpressionSymbol(binary) => binary.operator.apply(
+                binary.left.evaluate_memoised(values, memo)?,
+                binary.right.evaluate_memoised(values, memo)?,
+            ),
+            SymbolKind::CallExpressionSymbol(call) => {
+                let combine = match call.callee.kind() {
+                    SymbolKind::IdentifierSymbol(ident) if ident.name() == "min" => fmin,
+                    SymbolKind::IdentifierSymbol(ident) if ident.name() == "max" => fmax,
+                    _ => return None,
+                };
+                // evaluated left to right, like the chains they are built from
+                let mut result = None;
+                for arg in &call.arguments {
+                    let value = arg.evaluate_memoised(values, memo)?;
+                    result = Some(result.map_or(value, |acc| combine(acc, value)));
+                }
+                result?
+            }
+            SymbolKind::CallResultSymbol(_) => return None,
+        };
+        memo.insert(self.clone(), value);
+        Some(value)
+    }
+}
+
+impl PartialEq for Symbol {
+    /// Compares symbols by ID, which are equal for structurally equal
+    /// symbols.
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    /// Hashes the ID of the symbol.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
+
+impl PartialOrd for Symbol {
+    /// Orders symbols by ID, which is their order of creation.
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    /// Orders symbols by ID, which is their order of creation.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.id().cmp(&other.id())
+    }
+}
+
+impl std::fmt::Debug for Symbol {
+    /// Writes the ID of the symbol.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Symbol").field("id", &self.id()).finish()
+    }
+}
+
+impl Display for Symbol {
+    /// Writes a readable version of the symbol.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Printer::new().print(self))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
     LiteralSymbol(LiteralSymbol),
     IdentifierSymbol(IdentifierSymbol),
     UnaryExpressionSymbol(UnaryExpressionSymbol),
     BinaryExpressionSymbol(BinaryExpressionSymbol),
     CallExpressionSymbol(CallExpressionSymbol),
+    CallResultSymbol(CallResultSymbol),
 }
 
-impl Display for Symbol {
+impl Display for SymbolKind {
     /// Writes a readable version of the symbol.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Symbol::LiteralSymbol(literal) => literal.fmt(f),
-            Symbol::IdentifierSymbol(ident) => ident.fmt(f),
-            Symbol::UnaryExpressionSymbol(unary) => unary.fmt(f),
-            Symbol::BinaryExpressionSymbol(binary) => binary.fmt(f),
-            Symbol::CallExpressionSymbol(call) => call.fmt(f),
+            SymbolKind::LiteralSymbol(literal) => literal.fmt(f),
+            SymbolKind::IdentifierSymbol(ident) => ident.fmt(f),
+            SymbolKind::UnaryExpressionSymbol(unary) => unary.fmt(f),
+            SymbolKind::BinaryExpressionSymbol(binary) => binary.fmt(f),
+            SymbolKind::CallExpressionSymbol(call) => call.fmt(f),
+            SymbolKind::CallResultSymbol(result) => result.fmt(f),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum LiteralSymbol {
     Zero,
     NegZero,
     Infinity,
     NegInfinity,
+    NaN,
     One, // special one, only used for 1/x
 }
 
@@ -41,13 +307,57 @@ impl Display for LiteralSymbol {
             LiteralSymbol::NegZero => "-0.0",
             LiteralSymbol::Infinity => "Infinity",
             LiteralSymbol::NegInfinity => "-Infinity",
+            LiteralSymbol::NaN => "NaN",
             LiteralSymbol::One => "1",
         };
         write!(f, "{}", rep)
     }
 }
 
-#[derive(Debug)]
+impl LiteralSymbol {
+    /// Returns the value of the literal.
+    pub fn value(&self) -> f32 {
+        match self {
+            LiteralSymbol::Zero => 0.0,
+            LiteralSymbol::NegZero => -0.0,
+            LiteralSymbol::Infinity => f32::INFINITY,
+            LiteralSymbol::NegInfinity => f32::NEG_INFINITY,
+            LiteralSymbol::NaN => f32::NAN,
+            LiteralSymbol::One => 1.0,
+        }
+    }
+
+    /// Returns the literal with the given value, if there is one. The sign
+    /// of NaN is not tracked.
+    pub fn from_value(value: f32) -> Option<LiteralSymbol> {
+        if value.is_nan() {
+            Some(LiteralSymbol::NaN)
+        } else if value == 0.0 {
+            if value.is_sign_negative() {
+                Some(LiteralSymbol::NegZero)
+            } else {
+                Some(LiteralSymbol::Zero)
+            }
+        } else if value == f32::INFINITY {
+            Some(LiteralSymbol::Infinity)
+        } else if value == f32::NEG_INFINITY {
+            Some(LiteralSymbol::NegInfinity)
+        } else if value == 1.0 {
+            Some(LiteralSymbol::One)
+        } else {
+            None
+        }
+    }
+}
+
+impl From<LiteralSymbol> for Symbol {
+    /// Interns a literal symbol.
+    fn from(value: LiteralSymbol) -> Self {
+        Symbol::new(SymbolKind::LiteralSymbol(value))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct IdentifierSymbol {
     name: String,
 }
@@ -57,6 +367,11 @@ impl IdentifierSymbol {
     pub fn new(name: String) -> IdentifierSymbol {
         IdentifierSymbol { name }
     }
+
+    /// Returns the name of the identifier.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 }
 
 impl Display for IdentifierSymbol {
@@ -66,39 +381,76 @@ impl Display for IdentifierSymbol {
     }
 }
 
-#[derive(Debug)]
+impl From<IdentifierSymbol> for Symbol {
+    /// Interns an identifier symbol.
+    fn from(value: IdentifierSymbol) -> Self {
+        Symbol::new(SymbolKind::IdentifierSymbol(value))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum UnaryOperator {
-    Not,
+    /// Arithmetic negation, as done by the `!` opcode. This flips the sign
+    /// of zeros and infinities, e.g. -0.0 is -0.0 rather than 0.0.
+    Negate,
+}
+
+impl UnaryOperator {
+    /// Applies the operator to a value.
+    pub fn apply(&self, value: f32) -> f32 {
+        match self {
+            UnaryOperator::Negate => -value,
+        }
+    }
 }
 
 impl Display for UnaryOperator {
     /// Writes a readable version of the operator.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "!")
+        match self {
+            UnaryOperator::Negate => write!(f, "-"),
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct UnaryExpressionSymbol {
     operator: UnaryOperator,
-    argument: Box<Symbol>,
+    argument: Symbol,
 }
 
 impl UnaryExpressionSymbol {
     /// Creates a new unary expression symbol.
-    pub fn new(operator: UnaryOperator, argument: Box<Symbol>) -> UnaryExpressionSymbol {
+    pub fn new(operator: UnaryOperator, argument: Symbol) -> UnaryExpressionSymbol {
         UnaryExpressionSymbol { operator, argument }
     }
+
+    /// Returns the operator.
+    pub fn operator(&self) -> &UnaryOperator {
+        &self.operator
+    }
+
+    /// Returns the argument.
+    pub fn argument(&self) -> Symbol {
+        self.argument.clone()
+    }
 }
 
 impl Display for UnaryExpressionSymbol {
     /// Writes a readable version of the symbol.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}{}", self.operator, self.argument)
+        Symbol::from(self.clone()).fmt(f)
     }
 }
 
-#[derive(Debug)]
+impl From<UnaryExpressionSymbol> for Symbol {
+    /// Interns a unary expression symbol.
+    fn from(value: UnaryExpressionSymbol) -> Self {
+        Symbol::new(SymbolKind::UnaryExpressionSymbol(value))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum BinaryOperator {
     Add,
     Subtract,
@@ -106,6 +458,18 @@ pub enum BinaryOperator {
     Divide,
 }
 
+impl BinaryOperator {
+    /// Applies the operator to two values.
+    pub fn apply(&self, left: f32, right: f32) -> f32 {
+        match self {
+            BinaryOperator::Add => left + right,
+            BinaryOperator::Subtract => left - right,
+            BinaryOperator::Multiply => left * right,
+            BinaryOperator::Divide => left / right,
+        }
+    }
+}
+
 impl Display for BinaryOperator {
     /// Writes a readable version of the operator.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -119,61 +483,127 @@ impl Display for BinaryOperator {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct BinaryExpressionSymbol {
     operator: BinaryOperator,
-    left: Box<Symbol>,
-    right: Box<Symbol>,
+    left: Symbol,
+    right: Symbol,
 }
 
 impl BinaryExpressionSymbol {
     /// Creates a new binary expression symbol.
-    pub fn new(
-        operator: BinaryOperator,
-        left: Box<Symbol>,
-        right: Box<Symbol>,
-    ) -> BinaryExpressionSymbol {
+    pub fn new(operator: BinaryOperator, left: Symbol, right: Symbol) -> BinaryExpressionSymbol {
         BinaryExpressionSymbol {
             operator,
             left,
             right,
         }
     }
+
+    /// Returns the operator.
+    pub fn operator(&self) -> &BinaryOperator {
+        &self.operator
+    }
+
+    /// Returns the left operand.
+    pub fn left(&self) -> Symbol {
+        self.left.clone()
+    }
+
+    /// Returns the right operand.
+    pub fn right(&self) -> Symbol {
+        self.right.clone()
+    }
 }
 
 impl Display for BinaryExpressionSymbol {
-    /// Writes a readable version of the symbol.
+    /// Writes a readable version of the symbol, with only the parentheses
+    /// needed to keep its structure.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} {} {}", self.left, self.operator, self.right)
+        Symbol::from(self.clone()).fmt(f)
     }
 }
 
-#[derive(Debug)]
+impl From<BinaryExpressionSymbol> for Symbol {
+    /// Interns a binary expression symbol.
+    fn from(value: BinaryExpressionSymbol) -> Self {
+        Symbol::new(SymbolKind::BinaryExpressionSymbol(value))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct CallExpressionSymbol {
-    callee: Box<Symbol>,
+    callee: Symbol,
     arguments: Vec<Symbol>,
 }
 
 impl CallExpressionSymbol {
     /// Creates a new call expression symbol.
-    pub fn new(callee: Box<Symbol>, arguments: Vec<Symbol>) -> CallExpressionSymbol {
+    pub fn new(callee: Symbol, arguments: Vec<Symbol>) -> CallExpressionSymbol {
         CallExpressionSymbol { callee, arguments }
     }
+
+    /// Returns the callee.
+    pub fn callee(&self) -> Symbol {
+        self.callee.clone()
+    }
+
+    /// Returns the arguments.
+    pub fn arguments(&self) -> &Vec<Symbol> {
+        &self.arguments
+    }
 }
 
 impl Display for CallExpressionSymbol {
     /// Writes a readable version of the symbol.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}(", self.callee)?;
-        for (index, arg) in self.arguments.iter().enumerate() {
-            let separator = if index < self.arguments.len() - 1 {
-                ", "
-            } else {
-                ""
-            };
-            write!(f, "{}{}", arg, separator)?;
-        }
-        write!(f, ")")
+        Symbol::from(self.clone()).fmt(f)
+    }
+}
+
+impl From<CallExpressionSymbol> for Symbol {
+    /// Interns a call expression symbol.
+    fn from(value: CallExpressionSymbol) -> Self {
+        Symbol::new(SymbolKind::CallExpressionSymbol(value))
+    }
+}
+
+/// One of several results of a call, which a callee producing more than one
+/// result leaves on the stack in order of their index.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CallResultSymbol {
+    call: Symbol,
+    index: usize,
+}
+
+impl CallResultSymbol {
+    /// Creates a new call result symbol.
+    pub fn new(call: Symbol, index: usize) -> CallResultSymbol {
+        CallResultSymbol { call, index }
+    }
+
+    /// Returns the call.
+    pub fn call(&self) -> Symbol {
+        self.call.clone()
+    }
+
+    /// Returns the index of the result, where result 0 is pushed first.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl Display for CallResultSymbol {
+    /// Writes a readable version of the symbol.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Symbol::from(self.clone()).fmt(f)
+    }
+}
+
+impl From<CallResultSymbol> for Symbol {
+    /// Interns a call result symbol.
+    fn from(value: CallResultSymbol) -> Self {
+        Symbol::new(SymbolKind::CallResultSymbol(value))
     }
 }
 
@@ -182,18 +612,80 @@ impl From<&Operand> for Symbol {
     fn from(value: &Operand) -> Self {
         match value {
             Operand::Literal(literal) => match literal {
-                Literal::ZERO => Symbol::LiteralSymbol(LiteralSymbol::Zero),
-                Literal::INFINITY => Symbol::LiteralSymbol(LiteralSymbol::Infinity),
+                Literal::ZERO => Symbol::from(LiteralSymbol::Zero),
+                Literal::INFINITY => Symbol::from(LiteralSymbol::Infinity),
             },
             Operand::GlobalVariable(var) => {
-                Symbol::IdentifierSymbol(IdentifierSymbol::new(var.name().to_string()))
+                Symbol::from(IdentifierSymbol::new(var.name().to_string()))
             }
             Operand::Parameter(param) => {
-                Symbol::IdentifierSymbol(IdentifierSymbol::new(param.name().to_string()))
+                Symbol::from(IdentifierSymbol::new(param.name().to_string()))
             }
             Operand::FunctionReference(func) => {
-                Symbol::IdentifierSymbol(IdentifierSymbol::new(func.name().to_string()))
+                Symbol::from(IdentifierSymbol::new(func.name().to_string()))
+            }
+            Operand::RawBytes(raw) => {
+                Symbol::from(IdentifierSymbol::new(raw.to_string().to_string()))
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        BinaryExpressionSymbol, BinaryOperator, IdentifierSymbol, LiteralSymbol, Symbol, SymbolKind,
+    };
+
+    /// Returns an identifier symbol.
+    fn ident(name: &str) -> Symbol {
+        Symbol::from(IdentifierSymbol::new(name.to_string()))
+    }
+
+    /// Returns `left - right`.
+    fn subtract(left: Symbol, right: Symbol) -> Symbol {
+        Symbol::from(BinaryExpressionSymbol::new(
+            BinaryOperator::Subtract,
+            left,
+            right,
+        ))
+    }
+
+    #[test]
+    fn equal_expressions_share_a_symbol() {
+        let first = subtract(ident("param_0"), Symbol::from(LiteralSymbol::Infinity));
+        let second = subtract(ident("param_0"), Symbol::from(LiteralSymbol::Infinity));
+        assert_eq!(first.id(), second.id());
+
+        // subexpressions are shared too
+        let SymbolKind::BinaryExpressionSymbol(binary) = second.kind() else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(binary.left().id(), ident("param_0").id());
+    }
+
+    #[test]
+    fn symbols_are_equal_by_id() {
+        let symbol = subtract(ident("param_0"), ident("param_1"));
+        let swapped = subtract(ident("param_1"), ident("param_0"));
+        assert_eq!(symbol, subtract(ident("param_0"), ident("param_1")));
+        assert_eq!(symbol.clone(), symbol);
+        assert_ne!(symbol.id(), swapped.id());
+        assert_ne!(symbol, swapped);
+    }
+
+    #[test]
+    fn children_are_created_first() {
+        let symbol = subtract(ident("param_0"), ident("param_1"));
+        assert!(symbol.children().iter().all(|child| child < &symbol));
+    }
+
+    #[test]
+    fn unused_symbols_are_freed() {
+        let id = ident("global_a").id();
+        // nothing refers to the first symbol any more, so it is not reused
+        let symbol = ident("global_a");
+        assert_ne!(symbol.id(), id);
+        assert_eq!(ident("global_a").id(), symbol.id());
+    }
+}